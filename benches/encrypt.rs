@@ -0,0 +1,25 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use hcie_rs::{decrypt, encrypt, Diffusion, HcieParams, SecretKey};
+use ndarray::Array2;
+
+// encryption and decryption of a 4K grayscale image, whose blocks are
+// permuted on every available thread
+fn bench_4k(c: &mut Criterion) {
+    let key = SecretKey::new(0.1234, 3.97).unwrap();
+    let f = Array2::from_shape_fn((2160, 3840), |(i, j)| (i * 3840 + j) as u8);
+    let mut group = c.benchmark_group("4k");
+    group.sample_size(10);
+    for (name, params) in [
+        ("8x8", HcieParams::new(8, 8)),
+        ("32x32", HcieParams::new(32, 32)),
+        ("32x32 xor", HcieParams { diffusion: Diffusion::Xor, ..HcieParams::new(32, 32) }),
+    ] {
+        group.bench_function(format!("encrypt {}", name), |b| b.iter(|| encrypt(&f, &params, &key).unwrap()));
+        let encrypted = encrypt(&f, &params, &key).unwrap();
+        group.bench_function(format!("decrypt {}", name), |b| b.iter(|| decrypt(&encrypted, &params, &key).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_4k);
+criterion_main!(benches);
@@ -0,0 +1,142 @@
+//! Arbitrary-precision fixed-point numbers in [0, 1).
+//!
+//! Only what the high-precision logistic map needs: conversions, the
+//! complement `1 - x` and truncated multiplication. Since the orbit stays in
+//! [0, 1], a fixed-point format with enough bits serves as a big float.
+
+use crate::error::Error;
+
+/// A number in [0, 1) with `64 * limbs.len()` fractional bits.
+///
+/// The limbs are stored least significant first; the value is
+/// `sum(limbs[i] * 2^(64 i)) / 2^(64 n)`. Results of operations are
+/// truncated towards 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigFixed {
+    limbs: Vec<u64>,
+}
+
+impl BigFixed {
+    /// Zero, with at least `precision` fractional bits.
+    pub fn zero(precision: usize) -> Self {
+        Self { limbs: vec![0; precision.div_ceil(64).max(1)] }
+    }
+
+    /// Number of fractional bits.
+    pub fn precision(&self) -> usize {
+        64 * self.limbs.len()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.iter().all(|&l| l == 0)
+    }
+
+    /// Converts `x`, truncating the bits below `2^-precision`.
+    ///
+    /// The 53 significant bits of an `f64` at least `2^-k` end at or above
+    /// `2^-(k + 52)`, so 64 bits hold `x` exactly down to about `2^-11`;
+    /// smaller values need `52 - log2(x)` bits.
+    ///
+    /// # Panics
+    ///
+    /// If `x` is not in [0, 1).
+    pub fn from_f64(x: f64, precision: usize) -> Self {
+        assert!((0.0..1.0).contains(&x));
+        let mut r = Self::zero(precision);
+        let mut x = x;
+        for limb in r.limbs.iter_mut().rev() {
+            // multiplying by a power of 2 and taking the floor are exact
+            x *= 2f64.powi(64);
+            *limb = x as u64;
+            x -= *limb as f64;
+        }
+        r
+    }
+
+    /// Parses a decimal number `d.ddd...`, returning its integer part and
+    /// its fractional part, truncated to `precision` bits.
+    pub fn parse_decimal(s: &str, precision: usize) -> Result<(u64, Self), Error> {
+        let invalid = || Error::InvalidParams(format!("`{}` is not a decimal number", s));
+        let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+        if int.is_empty() || !int.bytes().all(|c| c.is_ascii_digit()) || !frac.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let int = int.parse().map_err(|_| invalid())?;
+        // the binary digits of 0.ddd... are the carries out of repeated doubling
+        let mut digits: Vec<u8> = frac.bytes().map(|c| c - b'0').collect();
+        let mut r = Self::zero(precision);
+        for limb in r.limbs.iter_mut().rev() {
+            for _ in 0..64 {
+                let mut carry = 0;
+                for d in digits.iter_mut().rev() {
+                    let v = 2 * *d + carry;
+                    *d = v % 10;
+                    carry = v / 10;
+                }
+                *limb = (*limb << 1) | carry as u64;
+            }
+        }
+        Ok((int, r))
+    }
+
+    /// Converts to `f64`, rounding to a number less than 1.
+    pub fn to_f64(&self) -> f64 {
+        self.limbs.iter().rev().take(2).enumerate().map(|(i, &l)| l as f64 / 2f64.powi(64 * (i as i32 + 1))).sum::<f64>().min(1.0 - f64::EPSILON / 2.0)
+    }
+
+    /// The `bits` (in `1..=64`) most significant fractional bits.
+    pub fn top_bits(&self, bits: u32) -> u64 {
+        self.limbs[self.limbs.len() - 1] >> (64 - bits)
+    }
+
+    /// `1 - self`, or the largest representable number if `self` is 0.
+    pub fn complement(&self) -> Self {
+        if self.is_zero() {
+            return Self { limbs: vec![u64::MAX; self.limbs.len()] };
+        }
+        // two's complement
+        let mut limbs: Vec<u64> = self.limbs.iter().map(|l| !l).collect();
+        for l in limbs.iter_mut() {
+            let (v, carry) = l.overflowing_add(1);
+            *l = v;
+            if !carry {
+                break;
+            }
+        }
+        Self { limbs }
+    }
+
+    /// `self * other`, truncated. Both must have the same precision.
+    pub fn mul(&self, other: &Self) -> Self {
+        let n = self.limbs.len();
+        assert_eq!(n, other.limbs.len());
+        let mut product = vec![0u64; 2 * n];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u128;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let v = a as u128 * b as u128 + product[i + j] as u128 + carry;
+                product[i + j] = v as u64;
+                carry = v >> 64;
+            }
+            product[i + n] = carry as u64;
+        }
+        Self { limbs: product.split_off(n) }
+    }
+
+    /// `k * self + other`, or `None` if the result is not less than 1.
+    pub fn mul_small_add(&self, k: u64, other: &Self) -> Option<Self> {
+        assert_eq!(self.limbs.len(), other.limbs.len());
+        let mut carry = 0u128;
+        let limbs = self
+            .limbs
+            .iter()
+            .zip(other.limbs.iter())
+            .map(|(&a, &b)| {
+                let v = a as u128 * k as u128 + b as u128 + carry;
+                carry = v >> 64;
+                v as u64
+            })
+            .collect();
+        (carry == 0).then_some(Self { limbs })
+    }
+}
@@ -0,0 +1,391 @@
+//! Chaotic maps used to generate the bit sequence.
+//!
+//! The logistic map of the original scheme is one implementation of
+//! [`ChaoticMap`]; the others are meant to compare the security of HCIE
+//! under different generators. [`MapKind`] selects one of them and derives
+//! its parameters from a [`SecretKey`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, keystream::Keystream, logistic::{BigLogisticMap, FixedLogisticMap, LogisticMap, SecretKey, BIG_PRECISION, MU_MIN}};
+
+/// A chaotic map whose orbit is turned into a bit sequence.
+pub trait ChaoticMap {
+    /// Iterates the map once and returns the new state, scaled to [0, 1].
+    fn next(&mut self) -> f64;
+
+    /// Iterates the map once and returns the first `bits` bits (at most 32)
+    /// of the new state following the binary point.
+    fn next_bits(&mut self, bits: u32) -> u64 {
+        // scaling by a power of 2 is exact
+        (self.next().fract() * 2f64.powi(bits as i32)) as u64
+    }
+}
+
+impl<M> ChaoticMap for Box<M>
+where
+    M: ChaoticMap + ?Sized,
+{
+    fn next(&mut self) -> f64 {
+        (**self).next()
+    }
+
+    fn next_bits(&mut self, bits: u32) -> u64 {
+        (**self).next_bits(bits)
+    }
+}
+
+/// Generates a binary sequence from `n_iter` iterations of `map`,
+/// 8 bits (most significant first) per iteration.
+pub fn bitsequence<M>(map: &mut M, n_iter: usize) -> Vec<u8>
+where
+    M: ChaoticMap + ?Sized,
+{
+    BitExtraction::default().bitsequence(map, 8 * n_iter)
+}
+
+/// How bits are extracted from every iterate `x` of a chaotic map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExtractionMethod {
+    /// The first `bits` bits of `x` following the binary point.
+    #[default]
+    Fraction,
+    /// A single bit, set if `x > 0.5`.
+    Threshold,
+    /// The last `bits` bits of the mantissa of `x` as an `f64`. These are
+    /// the bits most affected by rounding, so they differ between platforms
+    /// even more easily than the others.
+    Mantissa,
+}
+
+impl std::str::FromStr for ExtractionMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "fraction" => Ok(ExtractionMethod::Fraction),
+            "threshold" => Ok(ExtractionMethod::Threshold),
+            "mantissa" => Ok(ExtractionMethod::Mantissa),
+            _ => Err(Error::InvalidParams(format!("unknown extraction method `{}`", s))),
+        }
+    }
+}
+
+/// Extraction policy of the bit sequence.
+///
+/// More bits per iterate give a longer bit sequence for the same number of
+/// iterations, at the cost of correlation between the bits: the last bits
+/// of an iterate depend on rounding more than on the map. Skipping a
+/// transient discards the first iterates, which stay close to the key.
+/// The default, 8 bits of the fraction without transient, is the original
+/// scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BitExtraction {
+    pub method: ExtractionMethod,
+    /// Bits per iterate, in `1..=32` for [`ExtractionMethod::Fraction`] and
+    /// `1..=52` for [`ExtractionMethod::Mantissa`]; ignored by
+    /// [`ExtractionMethod::Threshold`].
+    pub bits: u32,
+    /// Iterations discarded before the first bits are taken.
+    pub skip: usize,
+}
+
+impl Default for BitExtraction {
+    fn default() -> Self {
+        Self { method: ExtractionMethod::Fraction, bits: 8, skip: 0 }
+    }
+}
+
+impl BitExtraction {
+    pub fn validate(&self) -> Result<(), Error> {
+        let max = match self.method {
+            ExtractionMethod::Fraction => 32,
+            ExtractionMethod::Threshold => return Ok(()),
+            ExtractionMethod::Mantissa => 52,
+        };
+        if !(1..=max).contains(&self.bits) {
+            return Err(Error::InvalidParams(format!("{} bits per iterate is not in 1..={} for this extraction method", self.bits, max)));
+        }
+        Ok(())
+    }
+
+    /// Number of bits taken from every iterate.
+    pub fn bits_per_iterate(&self) -> u32 {
+        match self.method {
+            ExtractionMethod::Threshold => 1,
+            _ => self.bits,
+        }
+    }
+
+    /// Iterates `map` once and returns the bits extracted from the new
+    /// state, in the [`bits_per_iterate`](Self::bits_per_iterate) least
+    /// significant bits.
+    pub fn extract<M>(&self, map: &mut M) -> u64
+    where
+        M: ChaoticMap + ?Sized,
+    {
+        match self.method {
+            ExtractionMethod::Fraction => map.next_bits(self.bits),
+            ExtractionMethod::Threshold => (map.next() > 0.5) as u64,
+            ExtractionMethod::Mantissa => map.next().to_bits() & ((1 << self.bits) - 1),
+        }
+    }
+
+    /// Generates the first `n_bits` bits of the [`Keystream`] of `map`.
+    pub fn bitsequence<M>(&self, map: &mut M, n_bits: usize) -> Vec<u8>
+    where
+        M: ChaoticMap + ?Sized,
+    {
+        Keystream::new(map, *self).take(n_bits).collect()
+    }
+}
+
+/// Tent map: x -> mu * min(x, 1 - x), chaotic for mu in (1, 2].
+pub struct TentMap {
+    pub x: f64,
+    pub mu: f64,
+}
+
+impl ChaoticMap for TentMap {
+    fn next(&mut self) -> f64 {
+        self.x = self.mu * self.x.min(1.0 - self.x);
+        self.x
+    }
+}
+
+/// Hénon map: (x, y) -> (1 - a x^2 + y, b x), chaotic for a = 1.4, b = 0.3.
+///
+/// Its attractor lies in [-1.5, 1.5] x [-0.4, 0.4]; the state returned is `(x + 1.5) / 3`.
+pub struct HenonMap {
+    pub x: f64,
+    pub y: f64,
+    pub a: f64,
+    pub b: f64,
+}
+
+impl ChaoticMap for HenonMap {
+    fn next(&mut self) -> f64 {
+        (self.x, self.y) = (1.0 - self.a * self.x * self.x + self.y, self.b * self.x);
+        ((self.x + 1.5) / 3.0).clamp(0.0, 1.0)
+    }
+}
+
+/// Chebyshev map: x -> cos(k arccos x) on [-1, 1], chaotic for k >= 2.
+///
+/// The state returned is `(x + 1) / 2`.
+pub struct ChebyshevMap {
+    pub x: f64,
+    pub k: f64,
+}
+
+impl ChaoticMap for ChebyshevMap {
+    fn next(&mut self) -> f64 {
+        self.x = (self.k * self.x.clamp(-1.0, 1.0).acos()).cos();
+        (self.x + 1.0) / 2.0
+    }
+}
+
+/// Piecewise linear chaotic map (PWLCM) with control parameter p in (0, 0.5).
+pub struct PiecewiseLinearMap {
+    pub x: f64,
+    pub p: f64,
+}
+
+impl ChaoticMap for PiecewiseLinearMap {
+    fn next(&mut self) -> f64 {
+        let p = self.p;
+        // the map is symmetric around 0.5
+        let x = self.x.min(1.0 - self.x);
+        self.x = if x < p { x / p } else { (x - p) / (0.5 - p) };
+        self.x
+    }
+}
+
+/// Skew tent map with peak at p in (0, 1).
+pub struct SkewTentMap {
+    pub x: f64,
+    pub p: f64,
+}
+
+impl ChaoticMap for SkewTentMap {
+    fn next(&mut self) -> f64 {
+        self.x = if self.x < self.p { self.x / self.p } else { (1.0 - self.x) / (1.0 - self.p) };
+        self.x
+    }
+}
+
+/// The chaotic map used to generate the bit sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MapKind {
+    #[default]
+    Logistic,
+    /// The logistic map in fixed-point arithmetic ([`FixedLogisticMap`]),
+    /// which generates the same bit sequence on every platform.
+    FixedLogistic,
+    /// The logistic map with [`BIG_PRECISION`] bits ([`BigLogisticMap`]).
+    BigLogistic,
+    Tent,
+    Henon,
+    Chebyshev,
+    PiecewiseLinear,
+    SkewTent,
+}
+
+impl MapKind {
+    pub const ALL: [MapKind; 8] = [
+        MapKind::Logistic,
+        MapKind::FixedLogistic,
+        MapKind::BigLogistic,
+        MapKind::Tent,
+        MapKind::Henon,
+        MapKind::Chebyshev,
+        MapKind::PiecewiseLinear,
+        MapKind::SkewTent,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MapKind::Logistic => "logistic",
+            MapKind::FixedLogistic => "fixed-logistic",
+            MapKind::BigLogistic => "big-logistic",
+            MapKind::Tent => "tent",
+            MapKind::Henon => "henon",
+            MapKind::Chebyshev => "chebyshev",
+            MapKind::PiecewiseLinear => "piecewise-linear",
+            MapKind::SkewTent => "skew-tent",
+        }
+    }
+
+    /// Builds the map from a key.
+    ///
+    /// `x_0` gives the initial state. The logistic maps use `mu` as is; for
+    /// the others, the control parameter is obtained by mapping `mu`, from
+    /// `[MU_MIN, 4]`, linearly onto a chaotic range of the map, so that the same key can be used with every map. Parameters that
+    /// make the map degenerate in floating point (e.g. a tent map with slope 2,
+    /// which shifts all the bits out of the state) are avoided.
+    pub fn build(&self, key: &SecretKey) -> Result<Box<dyn ChaoticMap>, Error> {
+        // keys built through their fields skip SecretKey::validate, so the
+        // orbit of every map is checked
+        check_orbit(&mut self.construct(key)?, self.name())?;
+        self.construct(key)
+    }
+
+    fn construct(&self, key: &SecretKey) -> Result<Box<dyn ChaoticMap>, Error> {
+        let SecretKey { x_0, mu } = *key;
+        let t = ((mu - MU_MIN) / (4.0 - MU_MIN)).clamp(0.0, 1.0);
+        Ok(match self {
+            MapKind::Logistic => Box::new(LogisticMap::new(key)),
+            MapKind::FixedLogistic => Box::new(FixedLogisticMap::new(key)),
+            MapKind::BigLogistic => Box::new(BigLogisticMap::new(key, BIG_PRECISION)?),
+            MapKind::Tent => Box::new(TentMap { x: x_0, mu: 1.9 + 0.099 * t }),
+            MapKind::Henon => Box::new(HenonMap { x: x_0 - 0.5, y: 0.4 * t - 0.2, a: 1.4, b: 0.3 }),
+            MapKind::Chebyshev => Box::new(ChebyshevMap { x: 2.0 * x_0 - 1.0, k: 4.0 + 4.0 * t }),
+            MapKind::PiecewiseLinear => Box::new(PiecewiseLinearMap { x: x_0, p: 0.05 + 0.4 * t }),
+            MapKind::SkewTent => Box::new(SkewTentMap { x: x_0, p: 0.05 + 0.4 * t }),
+        })
+    }
+}
+
+impl std::str::FromStr for MapKind {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        MapKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| Error::InvalidParams(format!("unknown chaotic map `{}`", s)))
+    }
+}
+
+// the orbit of every new key is iterated WARM_UP times to detect fixed
+// points and cycles of period up to MAX_PERIOD
+pub(crate) const WARM_UP: usize = 2048;
+const MAX_PERIOD: usize = 256;
+
+/// Iterates an orbit `WARM_UP` times, `next(k)` returning its state after
+/// `k + 1` iterations (or an error, e.g. if it leaves the domain of the
+/// map), and checks that it falls neither into a fixed point nor into a
+/// short cycle. `orbit` names the orbit in the error.
+pub(crate) fn check_aperiodic<F>(orbit: &str, mut next: F) -> Result<(), Error>
+where
+    F: FnMut(usize) -> Result<f64, Error>,
+{
+    let mut history = [0.0; MAX_PERIOD];
+    for k in 0..WARM_UP {
+        let x = next(k)?;
+        if let Some(p) = (1..=k.min(MAX_PERIOD)).find(|&p| history[(k - p) % MAX_PERIOD] == x) {
+            return Err(Error::WeakKey(format!("{} becomes periodic, with period {}, after {} iterations", orbit, p, k + 1)));
+        }
+        history[k % MAX_PERIOD] = x;
+    }
+    Ok(())
+}
+
+/// Iterates `map` for a while, checking that its orbit neither leaves
+/// [0, 1] nor falls into a fixed point or a short cycle.
+fn check_orbit(map: &mut dyn ChaoticMap, name: &str) -> Result<(), Error> {
+    check_aperiodic(&format!("the orbit of the {} map", name), |k| {
+        let x = map.next();
+        if !(0.0..=1.0).contains(&x) {
+            return Err(Error::WeakKey(format!("the orbit of the {} map leaves [0, 1] after {} iterations", name, k + 1)));
+        }
+        Ok(x)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ndarray::Array2;
+
+    use super::*;
+    use crate::{decrypt, encrypt, HcieParams};
+
+    #[test]
+    fn round_trip_with_every_map() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let f = Array2::from_shape_fn((40, 24), |(i, j)| (i * 24 + j) as u16);
+        for kind in MapKind::ALL {
+            let params = HcieParams { map: kind, ..HcieParams::new(8, 8) };
+            let c = encrypt(&f, &params, &key).unwrap();
+            assert_ne!(c.data, f, "{:?}", kind);
+            assert_eq!(decrypt(&c, &params, &key).unwrap(), f, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn map_names() {
+        for kind in MapKind::ALL {
+            assert_eq!(kind.name().parse::<MapKind>().unwrap(), kind);
+        }
+        assert!(matches!("lorenz".parse::<MapKind>(), Err(Error::InvalidParams(_))));
+    }
+
+    #[test]
+    fn rejects_degenerate_orbits() {
+        // x_0 = 1 is a fixed point, or reaches one, of every map but the
+        // Hénon map, which starts from x_0 - 0.5
+        let key = SecretKey { x_0: 1.0, mu: 3.9 };
+        for kind in MapKind::ALL.into_iter().filter(|&kind| kind != MapKind::Henon) {
+            assert!(matches!(kind.build(&key), Err(Error::WeakKey(_))), "{:?}", kind);
+        }
+        struct Cycle(usize);
+        impl ChaoticMap for Cycle {
+            fn next(&mut self) -> f64 {
+                self.0 += 1;
+                [0.1, 0.7, 0.3][self.0 % 3]
+            }
+        }
+        struct Escape(f64);
+        impl ChaoticMap for Escape {
+            fn next(&mut self) -> f64 {
+                self.0 *= 1.5;
+                self.0
+            }
+        }
+        assert!(matches!(check_orbit(&mut Cycle(0), "cycle"), Err(Error::WeakKey(_))));
+        assert!(matches!(check_orbit(&mut Escape(0.1), "escape"), Err(Error::WeakKey(_))));
+    }
+}
@@ -0,0 +1,194 @@
+use ndarray::Array2;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{error::Error, kdf::KdfParams};
+
+/// Version of the [`Header`] format.
+///
+/// Version 2 added the [`kdf`](Header::kdf), [`nonce`](Header::nonce) and
+/// [`digest`](Header::digest) fields, and requires the nonce; headers of
+/// version 1 have none of them, and are still read (and written, for images
+/// encrypted without a nonce). Unknown fields are rejected, so that a header
+/// is never decrypted without the information it records.
+pub const HEADER_VERSION: u32 = 2;
+
+/// Length of a [`Nonce`], in bytes.
+pub const NONCE_LEN: usize = 16;
+
+/// Public random value drawn for every encryption.
+///
+/// The keystream of an encryption is generated from a key derived from the
+/// secret key and the nonce, so that images encrypted with the same key are
+/// permuted differently, and recovering the permutation of one image (e.g.
+/// with [`get_permutation_matrix`](crate::get_permutation_matrix())) says
+/// nothing about the others.
+pub type Nonce = [u8; NONCE_LEN];
+
+/// Keyed digest of a plain image, see
+/// [`HcieParams::plaintext_dependent`](crate::HcieParams::plaintext_dependent).
+pub type Digest = [u8; 32];
+
+/// Draws a new nonce from the random number generator of the operating
+/// system, or fails with [`Error::Io`] if it cannot provide random bytes.
+pub fn random_nonce() -> Result<Nonce, Error> {
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).map_err(|e| Error::Io(format!("cannot draw a nonce: {}", e)))?;
+    Ok(nonce)
+}
+
+/// An encrypted image, together with the information needed
+/// (besides the key and the parameters) to decrypt it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ciphertext<T> {
+    /// The encrypted pixels. Their dimensions are the dimensions of the
+    /// original image rounded up to a multiple of the block size.
+    pub data: Array2<T>,
+    pub header: Header,
+}
+
+/// Public information about an encrypted image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Header {
+    pub version: u32,
+    /// Height of the original image.
+    pub m: usize,
+    /// Width of the original image.
+    pub n: usize,
+    /// How the key was derived from a passphrase, if it was.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kdf: Option<KdfParams>,
+    /// Nonce of the encryption, written in hexadecimal. It is required from
+    /// version 2 on; images encrypted without one (with a header of version
+    /// 1) are decrypted with the secret key itself.
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub nonce: Option<Nonce>,
+    /// Digest of the plain image, in hexadecimal, if the keystream depends on it.
+    #[serde(default, skip_serializing_if = "Option::is_none", serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub digest: Option<Digest>,
+}
+
+impl Header {
+    /// The header of an `m x n` image encrypted without a nonce, e.g. by
+    /// [`encrypt_with`](crate::encrypt_with), which keeps version 1.
+    pub fn new(m: usize, n: usize) -> Self {
+        Self { version: 1, m, n, kdf: None, nonce: None, digest: None }
+    }
+
+    /// The header of an `m x n` image encrypted with `nonce` and, if the
+    /// keystream depends on the image, `digest`.
+    pub fn seeded(m: usize, n: usize, nonce: Nonce, digest: Option<Digest>) -> Self {
+        Self { version: HEADER_VERSION, m, n, kdf: None, nonce: Some(nonce), digest }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let header: Self = serde_json::from_str(json).map_err(|e| Error::InvalidHeader(e.to_string()))?;
+        if !(1..=HEADER_VERSION).contains(&header.version) {
+            return Err(Error::InvalidHeader(format!("unsupported version {}", header.version)));
+        }
+        // without its nonce, the image would be decrypted with a wrong keystream
+        if header.version >= 2 && header.nonce.is_none() {
+            return Err(Error::InvalidHeader(format!("missing nonce in a header of version {}", header.version)));
+        }
+        Ok(header)
+    }
+}
+
+fn to_hex<S, const N: usize>(bytes: &Option<[u8; N]>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let hex: String = bytes.unwrap_or([0; N]).iter().map(|b| format!("{:02x}", b)).collect();
+    serializer.serialize_str(&hex)
+}
+
+fn from_hex<'de, D, const N: usize>(deserializer: D) -> Result<Option<[u8; N]>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let hex = String::deserialize(deserializer)?;
+    let invalid = || serde::de::Error::custom(format!("invalid hexadecimal string `{}`", hex));
+    if hex.len() != 2 * N || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut bytes = [0; N];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(Some(bytes))
+}
+
+impl<T> Ciphertext<T> {
+    /// Wraps encrypted pixels that were not padded, nor encrypted with a nonce.
+    pub fn unpadded(data: Array2<T>) -> Self {
+        let (m, n) = data.dim();
+        Self { data, header: Header::new(m, n) }
+    }
+}
+
+/// Pads `f` to `m_pad x n_pad` by repeating its last row and column.
+pub(crate) fn pad<T>(f: &Array2<T>, m_pad: usize, n_pad: usize) -> Array2<T>
+where
+    T: Copy,
+{
+    let (m, n) = f.dim();
+    Array2::from_shape_fn((m_pad, n_pad), |(i, j)| f[[i.min(m - 1), j.min(n - 1)]])
+}
+
+/// Crops the top left `m x n` corner of `f`.
+pub(crate) fn crop<T>(f: Array2<T>, m: usize, n: usize) -> Array2<T>
+where
+    T: Copy,
+{
+    if f.dim() == (m, n) {
+        f
+    } else {
+        f.slice(ndarray::s![..m, ..n]).to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decrypt, encrypt, HcieParams, SecretKey};
+
+    #[test]
+    fn header_versions() {
+        let header = Header::seeded(70, 45, [7; NONCE_LEN], Some([9; 32]));
+        assert_eq!(Header::from_json(&header.to_json()).unwrap(), header);
+        // headers written before the nonce have no other fields, and are
+        // still written without one
+        let old = Header::from_json(r#"{"version": 1, "m": 70, "n": 45}"#).unwrap();
+        assert_eq!(old, Header::new(70, 45));
+        assert_eq!(Header::from_json(&old.to_json()).unwrap(), old);
+        let no_nonce = r#"{"version": 2, "m": 70, "n": 45}"#;
+        assert!(matches!(Header::from_json(no_nonce), Err(Error::InvalidHeader(_))));
+        for version in [0, HEADER_VERSION + 1] {
+            assert!(Header::from_json(&format!(r#"{{"version": {}, "m": 70, "n": 45}}"#, version)).is_err());
+        }
+        let unknown = r#"{"version": 2, "m": 70, "n": 45, "salt": "00"}"#;
+        assert!(matches!(Header::from_json(unknown), Err(Error::InvalidHeader(_))));
+    }
+
+    #[test]
+    fn pads_to_the_block_size() {
+        let f = Array2::from_shape_fn((5, 3), |(i, j)| (i * 3 + j) as u8);
+        let padded = pad(&f, 8, 4);
+        // the last row and column are repeated
+        assert_eq!(padded.slice(ndarray::s![..5, ..3]), f);
+        assert_eq!((padded[[7, 3]], padded[[6, 1]], padded[[2, 3]]), (f[[4, 2]], f[[4, 1]], f[[2, 2]]));
+        assert_eq!(crop(padded, 5, 3), f);
+
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let params = HcieParams::new(8, 8);
+        assert_eq!(params.padded_dim(64, 64), (64, 64));
+        let f = Array2::from_shape_fn((70, 45), |(i, j)| (i * 45 + j) as u16);
+        let c = encrypt(&f, &params, &key).unwrap();
+        assert_eq!((c.data.dim(), c.header.m, c.header.n), ((72, 48), 70, 45));
+        assert_eq!(decrypt(&c, &params, &key).unwrap(), f);
+    }
+}
@@ -0,0 +1,154 @@
+use image::{ImageBuffer, Pixel, Rgb, RgbImage, Rgba, RgbaImage};
+use ndarray::Array2;
+
+use crate::{ciphertext::{random_nonce, Ciphertext}, diffusion::Substitute, encrypt::{check_digest, decrypt_unchecked, encrypt_seeded, level_keys}, kdf::{channel_key, image_digest}, error::Error, img_array::{img_to_pixels, pixels_to_img}, logistic::SecretKey, params::HcieParams};
+
+/// How the channels of a colour image are permuted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Every channel is permuted independently, with a keystream
+    /// derived from the secret key for that channel (see [`channel_key`]).
+    PerChannel,
+    /// Whole pixels are permuted, so that they keep their colour.
+    Joint,
+}
+
+/// Encrypts an RGB image.
+pub fn encrypt_rgb(img: &RgbImage, mode: ColorMode, params: &HcieParams, key: &SecretKey) -> Result<Ciphertext<Rgb<u8>>, Error> {
+    encrypt_color(img, mode, params, key)
+}
+
+/// Decrypts an RGB image produced by [`encrypt_rgb`] with the same mode, parameters and key.
+pub fn decrypt_rgb(c: &Ciphertext<Rgb<u8>>, mode: ColorMode, params: &HcieParams, key: &SecretKey) -> Result<RgbImage, Error> {
+    decrypt_color(c, mode, params, key)
+}
+
+/// Encrypts an RGBA image. The alpha channel is treated as any other channel.
+pub fn encrypt_rgba(img: &RgbaImage, mode: ColorMode, params: &HcieParams, key: &SecretKey) -> Result<Ciphertext<Rgba<u8>>, Error> {
+    encrypt_color(img, mode, params, key)
+}
+
+/// Decrypts an RGBA image produced by [`encrypt_rgba`] with the same mode, parameters and key.
+pub fn decrypt_rgba(c: &Ciphertext<Rgba<u8>>, mode: ColorMode, params: &HcieParams, key: &SecretKey) -> Result<RgbaImage, Error> {
+    decrypt_color(c, mode, params, key)
+}
+
+/// Encrypts an image with any pixel type (e.g. 16-bit RGB).
+///
+/// Use [`pixels_to_img`] to turn the encrypted pixels into an image.
+pub fn encrypt_color<P>(img: &ImageBuffer<P, Vec<P::Subpixel>>, mode: ColorMode, params: &HcieParams, key: &SecretKey) -> Result<Ciphertext<P>, Error>
+where
+    P: Pixel + Substitute,
+    P::Subpixel: Substitute,
+{
+    let pixels = img_to_pixels(img);
+    // the channels share the nonce and the digest of the header
+    let nonce = random_nonce()?;
+    let digest = params.plaintext_dependent.then(|| image_digest(key, &pixels));
+    match mode {
+        ColorMode::PerChannel => {
+            let channels = split_channels(&pixels)
+                .iter()
+                .enumerate()
+                .map(|(c, channel)| encrypt_seeded(channel, params, &level_keys(params, &channel_key(key, c)), &nonce, digest))
+                .collect::<Result<Vec<_>, _>>()?;
+            let data = merge_channels(&channels.iter().map(|c| c.data.clone()).collect::<Vec<_>>());
+            Ok(Ciphertext { data, header: channels[0].header.clone() })
+        }
+        ColorMode::Joint => encrypt_seeded(&pixels, params, &level_keys(params, key), &nonce, digest),
+    }
+}
+
+/// Decrypts an image produced by [`encrypt_color`] with the same mode, parameters and key.
+pub fn decrypt_color<P>(c: &Ciphertext<P>, mode: ColorMode, params: &HcieParams, key: &SecretKey) -> Result<ImageBuffer<P, Vec<P::Subpixel>>, Error>
+where
+    P: Pixel + Substitute,
+    P::Subpixel: Substitute,
+{
+    let pixels = match mode {
+        ColorMode::PerChannel => {
+            let channels = split_channels(&c.data)
+                .into_iter()
+                .enumerate()
+                .map(|(i, data)| decrypt_unchecked(&Ciphertext { data, header: c.header.clone() }, params, &level_keys(params, &channel_key(key, i))))
+                .collect::<Result<Vec<_>, _>>()?;
+            merge_channels(&channels)
+        }
+        ColorMode::Joint => decrypt_unchecked(c, params, &level_keys(params, key))?,
+    };
+    check_digest(&pixels, &c.header, key)?;
+    Ok(pixels_to_img(&pixels))
+}
+
+fn split_channels<P>(pixels: &Array2<P>) -> Vec<Array2<P::Subpixel>>
+where
+    P: Pixel,
+{
+    (0..P::CHANNEL_COUNT as usize)
+        .map(|c| pixels.map(|pixel| pixel.channels()[c]))
+        .collect()
+}
+
+fn merge_channels<P>(channels: &[Array2<P::Subpixel>]) -> Array2<P>
+where
+    P: Pixel,
+{
+    Array2::from_shape_fn(channels[0].dim(), |idx| {
+        let values = channels.iter().map(|c| c[idx]).collect::<Vec<_>>();
+        *P::from_slice(&values)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Diffusion;
+
+    #[test]
+    fn round_trips() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let rgb = RgbImage::from_fn(45, 70, |x, y| Rgb([x as u8, y as u8, (x * y) as u8]));
+        let rgba = RgbaImage::from_fn(45, 70, |x, y| Rgba([x as u8, y as u8, (x + y) as u8, (x * 3) as u8]));
+        for params in [HcieParams::new(8, 8), HcieParams { diffusion: Diffusion::Xor, plaintext_dependent: true, ..HcieParams::new(16, 8) }] {
+            for mode in [ColorMode::PerChannel, ColorMode::Joint] {
+                let c = encrypt_rgb(&rgb, mode, &params, &key).unwrap();
+                assert_eq!(decrypt_rgb(&c, mode, &params, &key).unwrap(), rgb, "{:?} {:?}", mode, params);
+                let c = encrypt_rgba(&rgba, mode, &params, &key).unwrap();
+                assert_eq!(decrypt_rgba(&c, mode, &params, &key).unwrap(), rgba, "{:?} {:?}", mode, params);
+            }
+        }
+        let rgb16 = ImageBuffer::from_fn(20, 33, |x, y| Rgb([x as u16 * 1000, y as u16 * 1000, 7u16]));
+        for mode in [ColorMode::PerChannel, ColorMode::Joint] {
+            let c = encrypt_color(&rgb16, mode, &HcieParams::new(4, 4), &key).unwrap();
+            assert_eq!(decrypt_color(&c, mode, &HcieParams::new(4, 4), &key).unwrap(), rgb16);
+        }
+    }
+
+    #[test]
+    fn any_valid_key_encrypts_per_channel() {
+        // the second and third channel keys of the former derivation, by
+        // steps of the golden ratio, were weak
+        let key = SecretKey::new(0.5629407719251134, 3.6730633005442614).unwrap();
+        let rgb = RgbImage::from_fn(16, 16, |x, y| Rgb([x as u8, y as u8, (x * y) as u8]));
+        let params = HcieParams::new(8, 8);
+        let c = encrypt_rgb(&rgb, ColorMode::PerChannel, &params, &key).unwrap();
+        assert_eq!(decrypt_rgb(&c, ColorMode::PerChannel, &params, &key).unwrap(), rgb);
+    }
+
+    #[test]
+    fn joint_mode_keeps_the_colours() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let rgb = RgbImage::from_fn(32, 32, |x, y| Rgb([x as u8, y as u8, (x * y) as u8]));
+        let params = HcieParams::new(8, 8);
+        let colours = |pixels: &Array2<Rgb<u8>>| {
+            let mut colours = pixels.iter().map(|p| p.0).collect::<Vec<_>>();
+            colours.sort();
+            colours
+        };
+        let joint = encrypt_rgb(&rgb, ColorMode::Joint, &params, &key).unwrap();
+        assert_eq!(colours(&joint.data), colours(&img_to_pixels(&rgb)));
+        let per_channel = encrypt_rgb(&rgb, ColorMode::PerChannel, &params, &key).unwrap();
+        assert_ne!(colours(&per_channel.data), colours(&img_to_pixels(&rgb)));
+        assert_ne!(decrypt_rgb(&per_channel, ColorMode::Joint, &params, &key).unwrap(), rgb);
+    }
+}
@@ -0,0 +1,231 @@
+//! Optional substitution of the pixel values, on top of the permutation.
+//!
+//! HCIE only moves pixels around, so an encrypted image has the histogram
+//! of the plain image. The diffusion layer also changes the value of every
+//! pixel, mixing it with the keystream and with the previous encrypted
+//! pixel, so that the encrypted histogram is flat.
+
+use std::str::FromStr;
+
+use image::{Luma, LumaA, Rgb, Rgba};
+use ndarray::ArrayViewMut2;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Substitution applied to the pixels of every block once it is permuted.
+///
+/// The pixels are visited block after block, in the order of the encrypted
+/// image, and in row-major order inside every block. Every word `p` of a
+/// pixel (a channel, or the bits of a float) becomes `c`, computed from the
+/// next word `k` of the keystream and the same word `c'` of the previous
+/// encrypted pixel (0 for the first pixel):
+///
+/// - [`Diffusion::Xor`]: `c = p ^ k ^ c'`;
+/// - [`Diffusion::Additive`]: `c = p + k + c'`, modulo `2^bits`.
+///
+/// A change in a pixel thus changes every encrypted pixel after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Diffusion {
+    /// Pixels are only permuted, as in the original scheme.
+    #[default]
+    None,
+    Xor,
+    Additive,
+}
+
+impl FromStr for Diffusion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "none" => Ok(Diffusion::None),
+            "xor" => Ok(Diffusion::Xor),
+            "additive" => Ok(Diffusion::Additive),
+            _ => Err(Error::InvalidParams(format!("unknown diffusion `{}`", s))),
+        }
+    }
+}
+
+impl Diffusion {
+    /// Diffuses `block`, in place. `prev` is the last encrypted pixel, and
+    /// `word(bits)` returns the next keystream word of `bits` bits.
+    pub(crate) fn forward<T, W>(&self, block: &mut ArrayViewMut2<T>, prev: &mut Option<T>, mut word: W)
+    where
+        T: Substitute,
+        W: FnMut(u32) -> u64,
+    {
+        if *self == Diffusion::None {
+            return;
+        }
+        for p in block.iter_mut() {
+            let c = self.mix(*p, *prev, |p, c_prev, bits| {
+                let k = word(bits);
+                match self {
+                    Diffusion::None => p,
+                    Diffusion::Xor => p ^ k ^ c_prev,
+                    Diffusion::Additive => p.wrapping_add(k).wrapping_add(c_prev),
+                }
+            });
+            *p = c;
+            *prev = Some(c);
+        }
+    }
+
+    /// Undoes [`forward`](Self::forward), given the same keystream words.
+    pub(crate) fn inverse<T, W>(&self, block: &mut ArrayViewMut2<T>, prev: &mut Option<T>, mut word: W)
+    where
+        T: Substitute,
+        W: FnMut(u32) -> u64,
+    {
+        if *self == Diffusion::None {
+            return;
+        }
+        for c in block.iter_mut() {
+            let p = self.mix(*c, *prev, |c, c_prev, bits| {
+                let k = word(bits);
+                match self {
+                    Diffusion::None => c,
+                    Diffusion::Xor => c ^ k ^ c_prev,
+                    Diffusion::Additive => c.wrapping_sub(k).wrapping_sub(c_prev),
+                }
+            });
+            *prev = Some(*c);
+            *c = p;
+        }
+    }
+
+    // applies f to the words of x and prev (0 if there is none), truncating
+    // the results to the width of the words
+    fn mix<T, F>(&self, x: T, prev: Option<T>, mut f: F) -> T
+    where
+        T: Substitute,
+        F: FnMut(u64, u64, u32) -> u64,
+    {
+        let mask = |bits: u32| u64::MAX >> (64 - bits);
+        match prev {
+            Some(prev) => x.zip_words(prev, |x, prev, bits| f(x, prev, bits) & mask(bits)),
+            None => x.zip_words(x, |x, _, bits| f(x, 0, bits) & mask(bits)),
+        }
+    }
+}
+
+/// Widths, in bits, of the words of `pixel`.
+pub(crate) fn word_widths<T>(pixel: T) -> Vec<u32>
+where
+    T: Substitute,
+{
+    let mut widths = Vec::new();
+    pixel.zip_words(pixel, |x, _, bits| {
+        widths.push(bits);
+        x
+    });
+    widths
+}
+
+/// Pixel types of the cipher, whose values can be substituted: every pixel
+/// is a sequence of words of at most 64 bits.
+///
+/// Any other `Copy` type can be encrypted with an empty implementation:
+/// its pixels then have no words, so they are only permuted, and
+/// [`Diffusion`] and plaintext-dependent keystreams are rejected for them.
+///
+/// Pixels are also `Send` and `Sync`, since the blocks of an image are
+/// encrypted on several threads.
+pub trait Substitute: Copy + Send + Sync {
+    /// Replaces every word `x` of `self` by `f(x, y, bits)`, where `y` is the
+    /// matching word of `other` and `bits` the width of the words. The result
+    /// of `f` must fit in `bits` bits.
+    ///
+    /// By default, the pixel has no words and is returned unchanged.
+    fn zip_words<F>(self, other: Self, f: F) -> Self
+    where
+        F: FnMut(u64, u64, u32) -> u64,
+    {
+        let _ = (other, f);
+        self
+    }
+}
+
+macro_rules! substitute_int {
+    ($($t:ty),*) => {$(
+        impl Substitute for $t {
+            fn zip_words<F>(self, other: Self, mut f: F) -> Self
+            where
+                F: FnMut(u64, u64, u32) -> u64,
+            {
+                f(self as u64, other as u64, <$t>::BITS) as $t
+            }
+        }
+    )*};
+}
+
+substitute_int!(u8, u16, u32, u64);
+
+// signed pixels are substituted as their two's complement bits
+macro_rules! substitute_signed {
+    ($($t:ty => $u:ty),*) => {$(
+        impl Substitute for $t {
+            fn zip_words<F>(self, other: Self, mut f: F) -> Self
+            where
+                F: FnMut(u64, u64, u32) -> u64,
+            {
+                f(self as $u as u64, other as $u as u64, <$t>::BITS) as $u as $t
+            }
+        }
+    )*};
+}
+
+substitute_signed!(i8 => u8, i16 => u16, i32 => u32, i64 => u64);
+
+impl Substitute for f32 {
+    fn zip_words<F>(self, other: Self, mut f: F) -> Self
+    where
+        F: FnMut(u64, u64, u32) -> u64,
+    {
+        f32::from_bits(f(self.to_bits() as u64, other.to_bits() as u64, 32) as u32)
+    }
+}
+
+impl Substitute for f64 {
+    fn zip_words<F>(self, other: Self, mut f: F) -> Self
+    where
+        F: FnMut(u64, u64, u32) -> u64,
+    {
+        f64::from_bits(f(self.to_bits(), other.to_bits(), 64))
+    }
+}
+
+impl<S, const N: usize> Substitute for [S; N]
+where
+    S: Substitute,
+{
+    fn zip_words<F>(mut self, other: Self, mut f: F) -> Self
+    where
+        F: FnMut(u64, u64, u32) -> u64,
+    {
+        for (x, y) in self.iter_mut().zip(other) {
+            *x = x.zip_words(y, &mut f);
+        }
+        self
+    }
+}
+
+macro_rules! substitute_pixel {
+    ($($p:ident),*) => {$(
+        impl<S> Substitute for $p<S>
+        where
+            S: Substitute,
+        {
+            fn zip_words<F>(self, other: Self, f: F) -> Self
+            where
+                F: FnMut(u64, u64, u32) -> u64,
+            {
+                $p(self.0.zip_words(other.0, f))
+            }
+        }
+    )*};
+}
+
+substitute_pixel!(Luma, LumaA, Rgb, Rgba);
@@ -1,83 +1,337 @@
-use ndarray::Array2;
-
-use crate::{sub_hcie::{SubHCIE, Operation}, logistic::{SecretKey, logistic_bitsequence}};
-
-fn hcie_apply(f_hcie: &mut Array2<u8>, f_table: &Array2<u8>, f: &Array2<u8>, sub_hcie: &mut SubHCIE) {
-    let m = f_hcie.shape()[0];
-    let n = f_hcie.shape()[1];
-    let s_m = f_table.shape()[0];
-    let s_n = f_table.shape()[1];
-    let b_n = n / s_n;
-    assert!(m % s_m == 0);
-    assert!(n % s_n == 0);
-    let mut order = 0;
-    // define f_sub here as all zeros
-    let mut f_sub = ndarray::Array::from_elem((s_m, s_n), 0);
-    for i in 0..s_m {
-        for j in 0..s_n {
-            if f_table[[i,j]] != 0 {
-                let dividend = (f_table[[i,j]] - 1) as usize;
-                let p = dividend / b_n;
-                let q = dividend % b_n;
-                for x in 0..s_m {
-                    for y in 0..s_n {
-                        f_sub[[x, y]] = f[[s_m*p + x, s_n * q + y]];
-                    }
-                }
-                sub_hcie.apply(&mut f_sub.view_mut()); 
-                let r = order / b_n;
-                let s = order % b_n;
-                for x in 0..s_m {
-                    for y in 0..s_n {
-                        f_hcie[[s_m*r + x, s_n*s + y]] = f_sub[[x, y]];
-                    }
-                }
-                order += 1;
-            }
+use std::{ops::Range, thread};
+
+use ndarray::{s, Array2, ArrayBase, ArrayView2, ArrayViewMut2, Data, Ix2, Zip};
+
+use crate::{ciphertext::{crop, pad, random_nonce, Ciphertext, Digest, Header, Nonce}, diffusion::{word_widths, Diffusion, Substitute}, error::Error, chaotic::ChaoticMap, kdf::{image_digest, level_key, nonce_key}, keystream::{Bits, Keystream, PackedBits}, logistic::SecretKey, params::{HcieParams, LevelKeys}, sub_hcie::{SubHCIE, Operation}};
+
+// blocks processed by every thread at a time: the keystream of a batch of
+// blocks is generated before they are permuted
+const BLOCKS_PER_THREAD: usize = 64;
+
+// the blocks are permuted where they are, in the order of the encrypted
+// image, and then moved to their places
+fn hcie_apply<T, M>(f: &mut ArrayViewMut2<T>, f_table: &Array2<u32>, params: &HcieParams, sub_hcie: &SubHCIE, keystreams: &mut [Keystream<M>])
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    let block_size = (params.s_m, params.s_n);
+    let pixels = params.levels() - 1;
+    // the keystream words of the diffusion of a block precede its bits
+    let widths = word_widths(f[[0, 0]]);
+    let words_per_block = if params.diffusion == Diffusion::None { 0 } else { block_size.0 * block_size.1 * widths.len() };
+    let blocks = table_blocks(f_table);
+    let mut views = f.exact_chunks_mut(block_size).into_iter().map(Some).collect::<Vec<_>>();
+    let mut prev = None;
+    for_each_batch(&blocks, params, &widths, keystreams, |batch, bits| {
+        let mut f_subs = batch.iter().map(|&(p, _)| views[p].take().unwrap()).collect::<Vec<_>>();
+        let words = parallel_map(&mut f_subs, |k, f_sub| {
+            let mut bits = bits(k);
+            let words = (0..words_per_block).map(|w| bits.word(pixels, widths[w % widths.len()])).collect::<Vec<_>>();
+            permute(f_sub, params, 1, sub_hcie, &mut bits);
+            words
+        });
+        // the diffusion of a block depends on the block before it
+        for (f_sub, words) in f_subs.iter_mut().zip(words) {
+            let mut next_word = words.iter();
+            params.diffusion.forward(f_sub, &mut prev, |_| *next_word.next().unwrap());
+        }
+    });
+    drop(views);
+    reorder_blocks(f, block_size, blocks);
+}
+
+// every block is decrypted with the keystream words and bits used to
+// encrypt it, which follow in the keystream those of the blocks before it;
+// the blocks are moved back to their places first, and then restored where
+// they are
+fn hcie_apply_rev<T, M>(f: &mut ArrayViewMut2<T>, f_table: &Array2<u32>, params: &HcieParams, sub_hcie: &SubHCIE, keystreams: &mut [Keystream<M>])
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    let block_size = (params.s_m, params.s_n);
+    let widths = word_widths(f[[0, 0]]);
+    let blocks = table_blocks(f_table);
+    // the last pixel of every encrypted block, before the blocks are moved
+    let last = f.exact_chunks(block_size).into_iter().map(|f_sub| f_sub[[block_size.0 - 1, block_size.1 - 1]]).collect::<Vec<_>>();
+    reorder_blocks(f, block_size, blocks.iter().map(|&(p, c)| (c, p)));
+    let mut views = f.exact_chunks_mut(block_size).into_iter().map(Some).collect::<Vec<_>>();
+    for_each_batch(&blocks, params, &widths, keystreams, |batch, bits| {
+        let mut f_subs = batch.iter().map(|&(p, _)| views[p].take().unwrap()).collect::<Vec<_>>();
+        parallel_map(&mut f_subs, |k, f_sub| {
+            let prev = batch[k].1.checked_sub(1).map(|c| last[c]);
+            decrypt_block(f_sub, prev, params, sub_hcie, &mut bits(k));
+        });
+    });
+}
+
+/// Decrypts, in place, a block encrypted after a block whose last pixel is
+/// `prev`, given its keystream bits.
+fn decrypt_block<T>(f_sub: &mut ArrayViewMut2<T>, mut prev: Option<T>, params: &HcieParams, sub_hcie: &SubHCIE, bits: &mut BlockBits)
+where
+    T: Substitute,
+{
+    let pixels = params.levels() - 1;
+    params.diffusion.inverse(f_sub, &mut prev, |width| bits.word(pixels, width));
+    unpermute(f_sub, params, 1, sub_hcie, bits);
+}
+
+/// Permutes the contents of a block of level `depth - 1`: the order of its
+/// `params.sub_blocks[depth - 1]` blocks, then their contents, down to the
+/// pixels of the blocks of the last level.
+///
+/// The smaller blocks are permuted where they are, and then moved to their
+/// places, so that `f` is permuted in place.
+fn permute<T>(f: &mut ArrayViewMut2<T>, params: &HcieParams, depth: usize, sub_hcie: &SubHCIE, bits: &mut BlockBits)
+where
+    T: Copy,
+{
+    let Some(&block_size) = params.sub_blocks.get(depth - 1) else {
+        if params.permutation.pixels() {
+            let bits = bits.take(depth, sub_hcie.bits_for(f.dim()));
+            sub_hcie.apply_bits(f, &bits);
         }
+        return;
+    };
+    let blocks = table_blocks(&block_table(f.dim(), block_size, params, |n| bits.take(depth, n)));
+    let mut views = f.exact_chunks_mut(block_size).into_iter().collect::<Vec<_>>();
+    for &(p, _) in &blocks {
+        permute(&mut views[p], params, depth + 1, sub_hcie, bits);
     }
+    drop(views);
+    reorder_blocks(f, block_size, blocks);
 }
 
-fn hcie_apply_rev(f: &mut Array2<u8>, f_table: &Array2<u8>, f_hcie: &Array2<u8>, sub_hcie: &mut SubHCIE) {
-    let m = f.shape()[0];
-    let n = f.shape()[1];
-    let s_m = f_table.shape()[0];
-    let s_n = f_table.shape()[1];
-    let b_m = m / s_m;
-    let b_n = n / s_n;
-    assert!(m % s_m == 0);
-    assert!(n % s_n == 0);
-    let mut order = b_m * b_n;
-    // define f_sub here as all zeros
-    let mut f_sub = ndarray::Array::from_elem((s_m, s_n), 0);
-    for i in (0..s_m).rev() {
-        for j in (0..s_n).rev() {
-            if f_table[[i,j]] != 0 {
-                order -= 1;
-                let dividend = (f_table[[i,j]] - 1) as usize;
-                let p = dividend / b_n;
-                let q = dividend % b_n;
-                for x in 0..s_m {
-                    for y in 0..s_n {
-                        f_sub[[x, y]] = f_hcie[[s_m*p + x, s_n * q + y]];
-                    }
-                }
-                sub_hcie.apply(&mut f_sub.view_mut()); 
-                let r = order / b_n;
-                let s = order % b_n;
-                for x in 0..s_m {
-                    for y in 0..s_n {
-                        f[[s_m*r + x, s_n*s + y]] = f_sub[[x, y]];
-                    }
-                }
+/// Undoes [`permute`], reading the keystream in the same order.
+fn unpermute<T>(f: &mut ArrayViewMut2<T>, params: &HcieParams, depth: usize, sub_hcie: &SubHCIE, bits: &mut BlockBits)
+where
+    T: Copy,
+{
+    let Some(&block_size) = params.sub_blocks.get(depth - 1) else {
+        if params.permutation.pixels() {
+            let bits = bits.take(depth, sub_hcie.bits_for(f.dim()));
+            sub_hcie.apply_bits(f, &bits);
+        }
+        return;
+    };
+    let blocks = table_blocks(&block_table(f.dim(), block_size, params, |n| bits.take(depth, n)));
+    reorder_blocks(f, block_size, blocks.iter().map(|&(p, c)| (c, p)));
+    let mut views = f.exact_chunks_mut(block_size).into_iter().collect::<Vec<_>>();
+    for &(p, _) in &blocks {
+        unpermute(&mut views[p], params, depth + 1, sub_hcie, bits);
+    }
+}
+
+/// Moves the `s_m x s_n` blocks of `f`, in place: block `from` goes to
+/// block `to`, for every `(from, to)` pair of `moves`, and the other blocks
+/// stay where they are.
+///
+/// The cycles of the permutation are followed by swapping blocks, so no
+/// block is copied aside.
+fn reorder_blocks<T, I>(f: &mut ArrayViewMut2<T>, block_size: (usize, usize), moves: I)
+where
+    I: IntoIterator<Item = (usize, usize)>,
+{
+    let mut blocks = f.exact_chunks_mut(block_size).into_iter().collect::<Vec<_>>();
+    let mut dest = (0..blocks.len()).collect::<Vec<_>>();
+    for (from, to) in moves {
+        dest[from] = to;
+    }
+    for i in 0..blocks.len() {
+        // the block at i reaches its place, and the block it replaces comes to i
+        while dest[i] != i {
+            let j = dest[i];
+            let (head, tail) = blocks.split_at_mut(i.max(j));
+            Zip::from(&mut head[i.min(j)]).and(&mut tail[0]).for_each(std::mem::swap);
+            dest.swap(i, j);
+        }
+    }
+}
+
+/// The keystream bits of a block, for every level, read in order.
+///
+/// With a single keystream shared by every level, the bits of all the
+/// levels are read from the same sequence.
+struct BlockBits<'a> {
+    levels: Vec<PackedBits<'a>>,
+}
+
+impl<'a> BlockBits<'a> {
+    /// The next `n` bits of level `depth`.
+    fn take(&mut self, depth: usize, n: usize) -> PackedBits<'a> {
+        let last = self.levels.len() - 1;
+        let (bits, rest) = self.levels[depth.min(last)].split_at(n);
+        self.levels[depth.min(last)] = rest;
+        bits
+    }
+
+    /// The next word of `width` bits of level `depth`, most significant bit first.
+    fn word(&mut self, depth: usize, width: u32) -> u64 {
+        let bits = self.take(depth, width as usize);
+        (0..bits.len()).fold(0, |w, i| (w << 1) | bits.bit(i) as u64)
+    }
+}
+
+/// Number of keystream bits read by every level to encrypt one `s_m x s_n`
+/// block, whose pixels have words of `widths` bits. Every block reads the
+/// same number of bits, so the bits of a block start at a fixed offset.
+fn block_bits(params: &HcieParams, widths: &[u32]) -> Vec<usize> {
+    let sub_hcie = SubHCIE::new(params, Operation::Encrypt);
+    let blocks = params.blocks();
+    let mut bits = vec![0; params.levels()];
+    // number of blocks of the current level in a block of the first level
+    let mut count = 1;
+    for depth in 1..blocks.len() {
+        let ((m, n), (s_m, s_n)) = (blocks[depth - 1], blocks[depth]);
+        if params.permutation.blocks() {
+            bits[depth] = count * sub_hcie.bits_for(table_dim(m, n, s_m, s_n));
+        }
+        count *= (m / s_m) * (n / s_n);
+    }
+    let pixels = params.levels() - 1;
+    if params.permutation.pixels() {
+        bits[pixels] += count * sub_hcie.bits_for(blocks[blocks.len() - 1]);
+    }
+    if params.diffusion != Diffusion::None {
+        bits[pixels] += params.s_m * params.s_n * widths.iter().sum::<u32>() as usize;
+    }
+    bits
+}
+
+/// Calls `g(batch, bits)` for consecutive batches of `blocks`, `(p, c)`
+/// pairs as listed by [`for_each_block`] and sorted by `c`, after reading the
+/// keystream of every block of the batch: `bits(k)` are the bits of the
+/// `k`-th block of the batch.
+///
+/// The keystreams must be at the bits of the first block, and the bits of
+/// the blocks that are not listed are skipped.
+fn for_each_batch<M, G>(blocks: &[(usize, usize)], params: &HcieParams, widths: &[u32], keystreams: &mut [Keystream<M>], mut g: G)
+where
+    M: ChaoticMap + ?Sized,
+    G: for<'b> FnMut(&'b [(usize, usize)], &'b (dyn Fn(usize) -> BlockBits<'b> + Sync)),
+{
+    let mut bits_per_block = block_bits(params, widths);
+    if keystreams.len() == 1 {
+        bits_per_block = vec![bits_per_block.iter().sum()];
+    }
+    let starts = keystreams.iter().map(Keystream::position).collect::<Vec<_>>();
+    // the bits of every block are packed from the start of a word
+    let words_per_block = bits_per_block.iter().map(|n| n.div_ceil(64)).collect::<Vec<_>>();
+    let mut buffers = vec![Vec::new(); keystreams.len()];
+    for batch in blocks.chunks(threads() * BLOCKS_PER_THREAD) {
+        for (((keystream, buffer), (&n, &words)), &start) in keystreams.iter_mut().zip(&mut buffers).zip(bits_per_block.iter().zip(&words_per_block)).zip(&starts) {
+            buffer.resize(batch.len() * words, 0);
+            for (&(_, c), bits) in batch.iter().zip(buffer.chunks_mut(words.max(1))) {
+                keystream.seek(start + c * n);
+                keystream.read_packed(bits, n);
             }
         }
+        let bits = |k: usize| BlockBits {
+            levels: (buffers.iter().zip(&bits_per_block).zip(&words_per_block))
+                .map(|((buffer, &n), &words)| PackedBits::new(&buffer[k * words..(k + 1) * words], n))
+                .collect(),
+        };
+        g(batch, &bits);
     }
 }
 
-fn pseudoimage(m: usize, n: usize, s_m: usize, s_n: usize) -> Array2<u8> {
-    assert!(m % s_m == 0);
-    assert!(n % s_n == 0);
+/// Number of threads used to permute the blocks.
+fn threads() -> usize {
+    #[cfg(test)]
+    if let Some(threads) = tests::THREADS.get() {
+        return threads;
+    }
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// `items.iter_mut().enumerate().map(g)`, computed on several threads.
+fn parallel_map<I, R, G>(items: &mut [I], g: G) -> Vec<R>
+where
+    I: Send,
+    R: Send,
+    G: Fn(usize, &mut I) -> R + Sync,
+{
+    let chunk = items.len().div_ceil(threads()).max(1);
+    if chunk == items.len() {
+        return items.iter_mut().enumerate().map(|(k, item)| g(k, item)).collect();
+    }
+    thread::scope(|scope| {
+        let g = &g;
+        let handles = items
+            .chunks_mut(chunk)
+            .enumerate()
+            .map(|(i, items)| scope.spawn(move || items.iter_mut().enumerate().map(|(k, item)| g(i * chunk + k, item)).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+/// The keystream of level `depth`, or the single keystream shared by
+/// every level.
+fn level<'a, 'b, M>(keystreams: &'a mut [Keystream<'b, M>], depth: usize) -> &'a mut Keystream<'b, M>
+where
+    M: ChaoticMap + ?Sized,
+{
+    let last = keystreams.len() - 1;
+    &mut keystreams[depth.min(last)]
+}
+
+/// The `(p, c)` pairs of [`for_each_block`].
+fn table_blocks(f_table: &Array2<u32>) -> Vec<(usize, usize)> {
+    let mut blocks = Vec::new();
+    for_each_block(f_table, |p, c| blocks.push((p, c)));
+    blocks
+}
+
+/// Calls `g(p, c)` for every block numbered in the permuted block order
+/// table, in row-major order of the table: block `p` of the plain image is
+/// block `c` of the encrypted one.
+fn for_each_block<G>(f_table: &Array2<u32>, mut g: G)
+where
+    G: FnMut(usize, usize),
+{
+    let mut order = 0;
+    for &entry in f_table.iter() {
+        if entry != 0 {
+            g((entry - 1) as usize, order);
+            order += 1;
+        }
+    }
+}
+
+/// The `index`-th block of size `s_m x s_n` of `f`, in row-major order.
+fn block<T>(f: &Array2<T>, (s_m, s_n): (usize, usize), index: usize) -> ArrayView2<'_, T> {
+    let b_n = f.ncols() / s_n;
+    let (p, q) = (index / b_n, index % b_n);
+    f.slice(s![s_m*p..s_m*(p + 1), s_n*q..s_n*(q + 1)])
+}
+
+/// The block order table of the `s_m x s_n` blocks of an `m x n` matrix,
+/// permuted (always in the direction of encryption) with the bits returned
+/// by `bits(n)`, unless `params.permutation` leaves the order of the blocks
+/// unchanged.
+fn block_table<'a, B>((m, n): (usize, usize), (s_m, s_n): (usize, usize), params: &HcieParams, bits: B) -> Array2<u32>
+where
+    B: FnOnce(usize) -> PackedBits<'a>,
+{
+    let mut f_table = pseudoimage(m, n, s_m, s_n);
+    if params.permutation.blocks() {
+        let sub_hcie = SubHCIE::new(params, Operation::Encrypt);
+        let bits = bits(sub_hcie.bits_for(f_table.dim()));
+        sub_hcie.apply_bits(&mut f_table.view_mut(), &bits);
+    }
+    f_table
+}
+
+/// Builds the block order table: the `b_m x b_n` blocks are numbered from 1
+/// in row-major order, and the remaining entries are 0.
+///
+/// The table is `s_m x s_n`, or larger when there are more than `s_m` rows
+/// (or `s_n` columns) of blocks.
+fn pseudoimage(m: usize, n: usize, s_m: usize, s_n: usize) -> Array2<u32> {
+    assert!(m.is_multiple_of(s_m));
+    assert!(n.is_multiple_of(s_n));
     let func = |x: usize, y: usize| {
         if x < m/s_m && y < n/s_n {
             x * (n / s_n) + y + 1
@@ -85,38 +339,648 @@ fn pseudoimage(m: usize, n: usize, s_m: usize, s_n: usize) -> Array2<u8> {
             0
         }
     };
-    ndarray::Array::from_shape_fn((s_m, s_n), |(i, j)| func(i, j) as u8)
+    let (t_m, t_n) = table_dim(m, n, s_m, s_n);
+    ndarray::Array::from_shape_fn((t_m, t_n), |(i, j)| func(i, j) as u32)
 }
 
-pub fn encrypt(f: &Array2<u8>, s_m: usize, s_n: usize, key: &SecretKey) -> Array2<u8> {
-    let m = f.shape()[0];
-    let n = f.shape()[1];
-    let n_iter = 2;
-    let l_b = (1+ m/s_m * n/s_n) * n_iter * (3*s_m + 3*s_n - 2);
-    let bit_sequence = logistic_bitsequence(key, l_b);
-    let mut sub_hcie = SubHCIE::new(key, n_iter, Operation::Encrypt, 4, 2, 1, bit_sequence, 0);
-    let mut f_table = pseudoimage(m, n, s_m, s_n);
-    sub_hcie.apply(&mut f_table.view_mut());
+/// The block order table of the `s_m x s_n` blocks of an `m x n` image,
+/// permuted with the first bits of `keystream`.
+fn top_table<M>((m, n): (usize, usize), params: &HcieParams, keystream: &mut Keystream<M>) -> Array2<u32>
+where
+    M: ChaoticMap + ?Sized,
+{
+    let mut bits = Vec::new();
+    block_table((m, n), (params.s_m, params.s_n), params, |n| {
+        bits.resize(n.div_ceil(64), 0);
+        keystream.read_packed(&mut bits, n);
+        PackedBits::new(&bits, n)
+    })
+}
 
-    let mut f_hcie = ndarray::Array::from_elem((m, n), 0);
-    hcie_apply(&mut f_hcie, &f_table, f, &mut sub_hcie);
-    f_hcie
+fn table_dim(m: usize, n: usize, s_m: usize, s_n: usize) -> (usize, usize) {
+    (s_m.max(m / s_m), s_n.max(n / s_n))
 }
 
-pub fn decrypt(f_hcie: &Array2<u8>, s_m: usize, s_n: usize, key: &SecretKey) -> Array2<u8> {
-    let m = f_hcie.shape()[0];
-    let n = f_hcie.shape()[1];
-    let n_iter = 2;
-    let l_b = (1+ m/s_m * n/s_n) * n_iter * (3*s_m + 3*s_n - 2);
-    let bit_sequence = logistic_bitsequence(key, l_b);
-    let mut sub_hcie = SubHCIE::new(key, n_iter, Operation::Encrypt, 1, 1, 1, bit_sequence, 0);
-    let mut f_table = pseudoimage(m, n, s_m, s_n);
-    sub_hcie.apply(&mut f_table.view_mut());
-    let init_offset = (n/s_n * m/s_m + 1) * n_iter * (3*s_m + 3*s_n - 2);
-    sub_hcie.set_offset(init_offset);
-    sub_hcie.set_op(Operation::Decrypt);
-
-    let mut f = ndarray::Array::from_elem((m, n), 0);
-    hcie_apply_rev(&mut f, &f_table, f_hcie, &mut sub_hcie);
-    f
-}
\ No newline at end of file
+/// Encrypts the `m x n` image `f` using blocks of size `params.s_m x params.s_n`.
+///
+/// The order of the blocks is permuted first, then the pixels inside every
+/// block (or, with [`HcieParams::sub_blocks`], the order of its smaller
+/// blocks, and so on down to the pixels of the smallest ones), whose values
+/// are then changed if `params.diffusion` is set. `params.permutation` can
+/// restrict the permutation to the blocks or to the pixels. `T` can be any pixel type
+/// implementing [`Substitute`]: `u8`, `i16`, `f32`, `[u8; 3]`, `Rgb<u8>`, ...,
+/// or a type of your own with an empty implementation, which is only permuted.
+///
+/// If `m` or `n` are not multiples of the block size, the image is padded by
+/// repeating its last row and column; the original size is kept in the
+/// [`Header`](crate::Header) of the result.
+///
+/// Every call draws a new [`Nonce`], recorded in the header, so encrypting
+/// the same image twice gives two different permutations.
+pub fn encrypt<T>(f: &Array2<T>, params: &HcieParams, key: &SecretKey) -> Result<Ciphertext<T>, Error>
+where
+    T: Substitute,
+{
+    encrypt_with_nonce(f, params, key, &random_nonce()?)
+}
+
+/// Encrypts `f` as [`encrypt`], with a given nonce.
+///
+/// A nonce must not be used twice with the same key: the images would be
+/// permuted identically, as without a nonce.
+pub fn encrypt_with_nonce<T>(f: &Array2<T>, params: &HcieParams, key: &SecretKey, nonce: &Nonce) -> Result<Ciphertext<T>, Error>
+where
+    T: Substitute,
+{
+    let digest = params.plaintext_dependent.then(|| image_digest(key, f));
+    encrypt_seeded(f, params, &level_keys(params, key), nonce, digest)
+}
+
+/// Encrypts `f` as [`encrypt`], with a key of its own for every level of the
+/// hierarchy (see [`HcieParams::levels`]), regardless of `params.level_keys`.
+///
+/// The digest of the image, if any, is computed with the first key.
+pub fn encrypt_with_keys<T>(f: &Array2<T>, params: &HcieParams, keys: &[SecretKey]) -> Result<Ciphertext<T>, Error>
+where
+    T: Substitute,
+{
+    check_levels(params, keys.len())?;
+    let digest = params.plaintext_dependent.then(|| image_digest(&keys[0], f));
+    encrypt_seeded(f, params, keys, &random_nonce()?, digest)
+}
+
+/// Encrypts `f` with the keys derived from `keys` (one per level, or a
+/// single one shared by every level), `nonce` and `digest`, which are
+/// recorded in the header.
+pub(crate) fn encrypt_seeded<T>(f: &Array2<T>, params: &HcieParams, keys: &[SecretKey], nonce: &Nonce, digest: Option<Digest>) -> Result<Ciphertext<T>, Error>
+where
+    T: Substitute,
+{
+    check_words(f, params, digest.is_some())?;
+    let mut c = encrypt_with_maps(f, params, &mut seeded_maps(params, keys, Some(nonce), digest.as_ref())?)?;
+    c.header = Header::seeded(c.header.m, c.header.n, *nonce, digest);
+    Ok(c)
+}
+
+/// Checks that the pixels of `f` have words to diffuse, if `params.diffusion`
+/// is set, and to `digest`: pixel types whose [`Substitute`] implementation
+/// has no words can only be permuted.
+fn check_words<T, S>(f: &ArrayBase<S, Ix2>, params: &HcieParams, digest: bool) -> Result<(), Error>
+where
+    T: Substitute,
+    S: Data<Elem = T>,
+{
+    let wordless = f.first().is_some_and(|&pixel| word_widths(pixel).is_empty());
+    if wordless && (params.diffusion != Diffusion::None || digest) {
+        return Err(Error::InvalidParams("the pixels have no words to diffuse or digest; implement `Substitute::zip_words` for them".to_string()));
+    }
+    Ok(())
+}
+
+/// The keys of the levels of the hierarchy: `key` itself, shared by every
+/// level, or a subkey per level, depending on `params.level_keys`.
+pub(crate) fn level_keys(params: &HcieParams, key: &SecretKey) -> Vec<SecretKey> {
+    match params.level_keys {
+        LevelKeys::Shared => vec![*key],
+        LevelKeys::Derived => (0..params.levels()).map(|level| level_key(key, level)).collect(),
+    }
+}
+
+/// Checks that there is a key or map per level of the hierarchy, or a
+/// single one.
+fn check_levels(params: &HcieParams, count: usize) -> Result<(), Error> {
+    if count != 1 && count != params.levels() {
+        return Err(Error::InvalidParams(format!("expected 1 or {} keys, one per level of the hierarchy, got {}", params.levels(), count)));
+    }
+    Ok(())
+}
+
+/// The maps of `params.map` generating the keystreams of `keys`, seeded
+/// with `nonce` and `digest`.
+pub(crate) fn seeded_maps(params: &HcieParams, keys: &[SecretKey], nonce: Option<&Nonce>, digest: Option<&Digest>) -> Result<Vec<Box<dyn ChaoticMap>>, Error> {
+    keys.iter().map(|key| params.map.build(&seeded_key(key, nonce, digest))).collect()
+}
+
+/// The key of the keystream seeded with `nonce` and `digest`, or `key`
+/// itself if there are neither (as in headers written before both).
+fn seeded_key(key: &SecretKey, nonce: Option<&Nonce>, digest: Option<&Digest>) -> SecretKey {
+    let seed: Vec<u8> = nonce.into_iter().flatten().chain(digest.into_iter().flatten()).copied().collect();
+    if seed.is_empty() {
+        *key
+    } else {
+        nonce_key(key, &seed)
+    }
+}
+
+/// Encrypts `f` as [`encrypt`], with the bit sequence generated by `map`
+/// instead of `params.map`, and no nonce.
+///
+/// `map` must be in its initial state; decrypting requires a map in the
+/// same state.
+pub fn encrypt_with<T, M>(f: &Array2<T>, params: &HcieParams, map: &mut M) -> Result<Ciphertext<T>, Error>
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    encrypt_keystreams(f, params, &mut [Keystream::new(map, params.extraction)])
+}
+
+/// Encrypts `f` as [`encrypt_with`], with a map per level of the hierarchy
+/// (or a single one, shared by every level).
+pub fn encrypt_with_maps<T, M>(f: &Array2<T>, params: &HcieParams, maps: &mut [M]) -> Result<Ciphertext<T>, Error>
+where
+    T: Substitute,
+    M: ChaoticMap,
+{
+    check_levels(params, maps.len())?;
+    let mut keystreams = maps.iter_mut().map(|map| Keystream::new(map, params.extraction)).collect::<Vec<_>>();
+    encrypt_keystreams(f, params, &mut keystreams)
+}
+
+fn encrypt_keystreams<T, M>(f: &Array2<T>, params: &HcieParams, keystreams: &mut [Keystream<M>]) -> Result<Ciphertext<T>, Error>
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    let (m_orig, n_orig) = f.dim();
+    params.validate_for(m_orig, n_orig)?;
+    check_words(f, params, false)?;
+    let (m, n) = params.padded_dim(m_orig, n_orig);
+    // the padded copy is encrypted in place
+    let mut f_hcie = pad(f, m, n);
+    encrypt_view(&mut f_hcie.view_mut(), params, keystreams);
+    Ok(Ciphertext { data: f_hcie, header: Header::new(m_orig, n_orig) })
+}
+
+fn encrypt_view<T, M>(f: &mut ArrayViewMut2<T>, params: &HcieParams, keystreams: &mut [Keystream<M>])
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    let sub_hcie = SubHCIE::new(params, Operation::Encrypt);
+    let f_table = top_table(f.dim(), params, level(keystreams, 0));
+    hcie_apply(f, &f_table, params, &sub_hcie, keystreams);
+}
+
+/// Encrypts `f` as [`encrypt`], in place, and returns the header of the
+/// encrypted image, e.g. for an image too large to fit twice in memory.
+///
+/// The blocks are permuted where they are, and then moved to their places
+/// by following the cycles of their permutation. `f` can be any view (of
+/// an [`Array2`], of a mapped file, ...); since it cannot be padded, its
+/// size must be a multiple of the block size.
+pub fn encrypt_in_place<T>(mut f: ArrayViewMut2<T>, params: &HcieParams, key: &SecretKey) -> Result<Header, Error>
+where
+    T: Substitute,
+{
+    let (m, n) = f.dim();
+    check_in_place(m, n, params)?;
+    check_words(&f, params, params.plaintext_dependent)?;
+    let nonce = random_nonce()?;
+    let digest = params.plaintext_dependent.then(|| image_digest(key, &f));
+    let mut maps = seeded_maps(params, &level_keys(params, key), Some(&nonce), digest.as_ref())?;
+    let mut keystreams = maps.iter_mut().map(|map| Keystream::new(map, params.extraction)).collect::<Vec<_>>();
+    encrypt_view(&mut f, params, &mut keystreams);
+    Ok(Header::seeded(m, n, nonce, digest))
+}
+
+/// Decrypts, in place, an image produced by [`encrypt_in_place`] (or by
+/// [`encrypt`], if it was not padded) with the same parameters and key.
+///
+/// If the digest in `header` does not match, the error is returned after
+/// `f` is decrypted.
+pub fn decrypt_in_place<T>(mut f: ArrayViewMut2<T>, header: &Header, params: &HcieParams, key: &SecretKey) -> Result<(), Error>
+where
+    T: Substitute,
+{
+    let (m, n) = f.dim();
+    check_in_place(m, n, params)?;
+    check_words(&f, params, false)?;
+    if (header.m, header.n) != (m, n) {
+        return Err(Error::InvalidDimensions(format!("encrypted image of size {}x{} does not match its header", m, n)));
+    }
+    let mut maps = seeded_maps(params, &level_keys(params, key), header.nonce.as_ref(), header.digest.as_ref())?;
+    let mut keystreams = maps.iter_mut().map(|map| Keystream::new(map, params.extraction)).collect::<Vec<_>>();
+    decrypt_view(&mut f, params, &mut keystreams);
+    check_digest(&f, header, key)
+}
+
+/// Checks that an `m x n` image can be encrypted in place, without padding.
+fn check_in_place(m: usize, n: usize, params: &HcieParams) -> Result<(), Error> {
+    params.validate_for(m, n)?;
+    if params.padded_dim(m, n) != (m, n) {
+        return Err(Error::InvalidDimensions(format!("image of size {}x{} cannot be padded in place to a multiple of the block size", m, n)));
+    }
+    Ok(())
+}
+
+/// Decrypts an image produced by [`encrypt`] with the same parameters and key,
+/// using the nonce recorded in its header.
+///
+/// If the header holds a digest of the plain image, the digest of the
+/// decrypted image is checked against it.
+pub fn decrypt<T>(c: &Ciphertext<T>, params: &HcieParams, key: &SecretKey) -> Result<Array2<T>, Error>
+where
+    T: Substitute,
+{
+    let f = decrypt_unchecked(c, params, &level_keys(params, key))?;
+    check_digest(&f, &c.header, key)?;
+    Ok(f)
+}
+
+/// Decrypts an image produced by [`encrypt_with_keys`] with the same
+/// parameters and keys.
+pub fn decrypt_with_keys<T>(c: &Ciphertext<T>, params: &HcieParams, keys: &[SecretKey]) -> Result<Array2<T>, Error>
+where
+    T: Substitute,
+{
+    check_levels(params, keys.len())?;
+    let f = decrypt_unchecked(c, params, keys)?;
+    check_digest(&f, &c.header, &keys[0])?;
+    Ok(f)
+}
+
+/// Decrypts `c` without checking its digest.
+pub(crate) fn decrypt_unchecked<T>(c: &Ciphertext<T>, params: &HcieParams, keys: &[SecretKey]) -> Result<Array2<T>, Error>
+where
+    T: Substitute,
+{
+    decrypt_with_maps(c, params, &mut seeded_maps(params, keys, c.header.nonce.as_ref(), c.header.digest.as_ref())?)
+}
+
+/// Checks that `f` matches the digest in `header`, if there is one.
+pub(crate) fn check_digest<T, S>(f: &ArrayBase<S, Ix2>, header: &Header, key: &SecretKey) -> Result<(), Error>
+where
+    T: Substitute,
+    S: Data<Elem = T>,
+{
+    match header.digest {
+        Some(digest) if image_digest(key, f) != digest => {
+            Err(Error::DigestMismatch("the decrypted image does not match the digest in the header; check the key and the parameters".to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Decrypts an image produced by [`encrypt_with`], given a map in the same
+/// initial state.
+pub fn decrypt_with<T, M>(c: &Ciphertext<T>, params: &HcieParams, map: &mut M) -> Result<Array2<T>, Error>
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    decrypt_keystreams(c, params, &mut [Keystream::new(map, params.extraction)])
+}
+
+/// Decrypts an image produced by [`encrypt_with_maps`], given maps in the
+/// same initial state.
+pub fn decrypt_with_maps<T, M>(c: &Ciphertext<T>, params: &HcieParams, maps: &mut [M]) -> Result<Array2<T>, Error>
+where
+    T: Substitute,
+    M: ChaoticMap,
+{
+    check_levels(params, maps.len())?;
+    let mut keystreams = maps.iter_mut().map(|map| Keystream::new(map, params.extraction)).collect::<Vec<_>>();
+    decrypt_keystreams(c, params, &mut keystreams)
+}
+
+fn decrypt_keystreams<T, M>(c: &Ciphertext<T>, params: &HcieParams, keystreams: &mut [Keystream<M>]) -> Result<Array2<T>, Error>
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    check_ciphertext(c, params)?;
+    check_words(&c.data, params, false)?;
+    let mut f = c.data.clone();
+    decrypt_view(&mut f.view_mut(), params, keystreams);
+    Ok(crop(f, c.header.m, c.header.n))
+}
+
+fn decrypt_view<T, M>(f: &mut ArrayViewMut2<T>, params: &HcieParams, keystreams: &mut [Keystream<M>])
+where
+    T: Substitute,
+    M: ChaoticMap + ?Sized,
+{
+    // the block order tables are rebuilt exactly as in `encrypt`, and the
+    // blocks are then restored
+    let sub_hcie = SubHCIE::new(params, Operation::Decrypt);
+    let f_table = top_table(f.dim(), params, level(keystreams, 0));
+    hcie_apply_rev(f, &f_table, params, &sub_hcie, keystreams);
+}
+
+fn check_ciphertext<T>(c: &Ciphertext<T>, params: &HcieParams) -> Result<(), Error> {
+    let (m, n) = c.data.dim();
+    params.validate_for(c.header.m, c.header.n)?;
+    if params.padded_dim(c.header.m, c.header.n) != (m, n) {
+        return Err(Error::InvalidDimensions(format!("encrypted image of size {}x{} does not match its header", m, n)));
+    }
+    Ok(())
+}
+
+/// Decrypts the pixels in `rows` and `cols` of an image produced by
+/// [`encrypt`], e.g. a tile shown by a viewer of a large image.
+///
+/// Only the `s_m x s_n` blocks of the plain image that overlap the region
+/// are decrypted: their positions in the encrypted image are found in the
+/// block order table, and the keystream is skipped up to their bits, which
+/// start at a fixed offset for every block. The cost is thus the cost of
+/// decrypting these blocks, plus iterating the map up to the last of them.
+///
+/// The digest of the image, if there is one, cannot be checked. Colour
+/// images must have been encrypted with [`ColorMode::Joint`](crate::ColorMode::Joint).
+pub fn decrypt_region<T>(c: &Ciphertext<T>, params: &HcieParams, key: &SecretKey, rows: Range<usize>, cols: Range<usize>) -> Result<Array2<T>, Error>
+where
+    T: Substitute,
+{
+    check_ciphertext(c, params)?;
+    check_words(&c.data, params, false)?;
+    if rows.is_empty() || cols.is_empty() || rows.end > c.header.m || cols.end > c.header.n {
+        return Err(Error::InvalidDimensions(format!("region {:?}x{:?} is empty or not inside the image of size {}x{}", rows, cols, c.header.m, c.header.n)));
+    }
+    let mut maps = seeded_maps(params, &level_keys(params, key), c.header.nonce.as_ref(), c.header.digest.as_ref())?;
+    let mut keystreams = maps.iter_mut().map(|map| Keystream::new(map, params.extraction)).collect::<Vec<_>>();
+    let f_hcie = &c.data;
+    let (s_m, s_n) = (params.s_m, params.s_n);
+    let b_n = f_hcie.ncols() / s_n;
+    let f_table = top_table(f_hcie.dim(), params, level(&mut keystreams, 0));
+    let overlaps = |start: usize, size: usize, range: &Range<usize>| start < range.end && range.start < start + size;
+    // the blocks are listed by increasing position in the encrypted image
+    let blocks = table_blocks(&f_table)
+        .into_iter()
+        .filter(|&(p, _)| overlaps(p / b_n * s_m, s_m, &rows) && overlaps(p % b_n * s_n, s_n, &cols))
+        .collect::<Vec<_>>();
+    let sub_hcie = SubHCIE::new(params, Operation::Decrypt);
+    let widths = word_widths(f_hcie[[0, 0]]);
+    // every pixel of f is overwritten
+    let mut f = Array2::from_elem((rows.len(), cols.len()), f_hcie[[0, 0]]);
+    for_each_batch(&blocks, params, &widths, &mut keystreams, |batch, bits| {
+        let mut f_subs = batch.iter().map(|&(_, c)| block(f_hcie, (s_m, s_n), c).to_owned()).collect::<Vec<_>>();
+        parallel_map(&mut f_subs, |k, f_sub| {
+            // the last pixel of the previous encrypted block
+            let prev = batch[k].1.checked_sub(1).map(|c| block(f_hcie, (s_m, s_n), c)[[s_m - 1, s_n - 1]]);
+            decrypt_block(&mut f_sub.view_mut(), prev, params, &sub_hcie, &mut bits(k));
+        });
+        for (&(p, _), f_sub) in batch.iter().zip(&f_subs) {
+            let (i, j) = (p / b_n * s_m, p % b_n * s_n);
+            let (i_0, i_1) = (i.max(rows.start), (i + s_m).min(rows.end));
+            let (j_0, j_1) = (j.max(cols.start), (j + s_n).min(cols.end));
+            f.slice_mut(s![i_0 - rows.start..i_1 - rows.start, j_0 - cols.start..j_1 - cols.start])
+                .assign(&f_sub.slice(s![i_0 - i..i_1 - i, j_0 - j..j_1 - j]));
+        }
+    });
+    Ok(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::get_permutation_matrix::{apply_permutation_matrix, get_permutation_matrix};
+    use crate::params::PermutationMode;
+
+    thread_local! {
+        // number of threads to use instead of the available parallelism
+        pub(super) static THREADS: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    // the cipher without sub-blocks, as a single pass over the blocks in
+    // the order of the encrypted image, every block reading its keystream
+    // right after the previous one
+    fn encrypt_sequential<T, M>(f: &Array2<T>, params: &HcieParams, keystreams: &mut [Keystream<M>]) -> Array2<T>
+    where
+        T: Substitute,
+        M: ChaoticMap + ?Sized,
+    {
+        let block_size = (params.s_m, params.s_n);
+        let mut sub_hcie = SubHCIE::new(params, Operation::Encrypt);
+        let mut f_table = pseudoimage(f.nrows(), f.ncols(), params.s_m, params.s_n);
+        sub_hcie.apply(&mut f_table.view_mut(), level(keystreams, 0));
+        let widths = word_widths(f[[0, 0]]);
+        let mut f_hcie = f.clone();
+        let mut prev = None;
+        for_each_block(&f_table, |p, c| {
+            let keystream = level(keystreams, 1);
+            let words = match params.diffusion {
+                Diffusion::None => Vec::new(),
+                _ => (0..block_size.0 * block_size.1 * widths.len()).map(|w| keystream.word(widths[w % widths.len()])).collect(),
+            };
+            let mut f_sub = block(f, block_size, p).to_owned();
+            sub_hcie.apply(&mut f_sub.view_mut(), keystream);
+            let mut words = words.into_iter();
+            params.diffusion.forward(&mut f_sub.view_mut(), &mut prev, |_| words.next().unwrap());
+            f_hcie.exact_chunks_mut(block_size).into_iter().nth(c).unwrap().assign(&f_sub);
+        });
+        f_hcie
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        // 1596 blocks, in several batches
+        let f = Array2::from_shape_fn((300, 333), |(i, j)| ((i * 333 + j) as u32).wrapping_mul(2654435761));
+        let padded = pad(&f, 304, 336);
+        for threads in [1, 3, 4] {
+            THREADS.set(Some(threads));
+            for keys in [LevelKeys::Shared, LevelKeys::Derived] {
+                for diffusion in [Diffusion::None, Diffusion::Xor, Diffusion::Additive] {
+                    let params = HcieParams { level_keys: keys, diffusion, ..HcieParams::new(8, 8) };
+                    let keys = level_keys(&params, &key);
+                    let c = encrypt_with_maps(&f, &params, &mut seeded_maps(&params, &keys, None, None).unwrap()).unwrap();
+                    let mut maps = seeded_maps(&params, &keys, None, None).unwrap();
+                    let mut keystreams = maps.iter_mut().map(|map| Keystream::new(map, params.extraction)).collect::<Vec<_>>();
+                    assert_eq!(c.data, encrypt_sequential(&padded, &params, &mut keystreams), "{} threads, {:?}", threads, params);
+                    assert_eq!(decrypt_with_maps(&c, &params, &mut seeded_maps(&params, &keys, None, None).unwrap()).unwrap(), f);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_at_several_ratios() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        // 1024 blocks, with 32 > 16 rows and columns of blocks; 225 blocks
+        // in 25 > 8 rows; a single block; and a table wider than tall
+        for ((m, n), (s_m, s_n)) in [((512, 512), (16, 16)), ((200, 72), (8, 8)), ((16, 16), (16, 16)), ((8, 300), (4, 4)), ((45, 70), (3, 7))] {
+            let f = Array2::from_shape_fn((m, n), |(i, j)| (i * n + j) as u32);
+            let params = HcieParams::new(s_m, s_n);
+            let (b_m, b_n) = (m / s_m, n / s_n);
+            let f_table = pseudoimage(m, n, s_m, s_n);
+            assert_eq!(f_table.dim(), table_dim(m, n, s_m, s_n));
+            let mut entries = f_table.iter().filter(|&&entry| entry != 0).copied().collect::<Vec<_>>();
+            entries.sort();
+            assert_eq!(entries, (1..=(b_m * b_n) as u32).collect::<Vec<_>>());
+            let c = encrypt(&f, &params, &key).unwrap();
+            assert_ne!(c.data, f);
+            assert_eq!(decrypt(&c, &params, &key).unwrap(), f, "{}x{} with {}x{} blocks", m, n, s_m, s_n);
+        }
+    }
+
+    #[test]
+    fn region_matches_decrypt() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        // 70x45 is padded to 72x48 with 8x8 blocks
+        let f = Array2::from_shape_fn((70, 45), |(i, j)| ((i * 45 + j) as u16).wrapping_mul(40503));
+        let base = HcieParams::new(8, 8);
+        let hierarchies = [
+            base.clone(),
+            HcieParams { sub_blocks: vec![(4, 4)], level_keys: LevelKeys::Derived, ..base.clone() },
+            HcieParams { diffusion: Diffusion::Additive, ..base.clone() },
+            HcieParams { sub_blocks: vec![(4, 4), (2, 2)], level_keys: LevelKeys::Derived, diffusion: Diffusion::Additive, ..base.clone() },
+        ];
+        for params in hierarchies {
+            let c = encrypt(&f, &params, &key).unwrap();
+            let plain = decrypt(&c, &params, &key).unwrap();
+            // a single pixel, a whole block, regions across block edges, up
+            // to the padded border, and the whole image
+            for (rows, cols) in [(0..1, 0..1), (8..16, 16..24), (5..19, 3..30), (60..70, 40..45), (69..70, 0..45), (0..70, 0..45)] {
+                let region = decrypt_region(&c, &params, &key, rows.clone(), cols.clone()).unwrap();
+                assert_eq!(region, plain.slice(s![rows.clone(), cols.clone()]), "{:?}x{:?} with {:?}", rows, cols, params);
+            }
+            for (rows, cols) in [(3..3, 0..10), (0..10, 7..7), (0..71, 0..10), (0..10, 40..46), (70..72, 0..8)] {
+                assert!(matches!(decrypt_region(&c, &params, &key, rows, cols), Err(Error::InvalidDimensions(_))));
+            }
+        }
+    }
+
+    #[test]
+    fn nonces_give_different_permutations() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let params = HcieParams::new(8, 8);
+        // distinct pixels, so that the permutation can be read off a pair
+        let f = Array2::from_shape_fn((64, 64), |(i, j)| (i * 64 + j) as u16);
+        let (c_1, c_2) = (encrypt(&f, &params, &key).unwrap(), encrypt(&f, &params, &key).unwrap());
+        assert_ne!(c_1.header.nonce, c_2.header.nonce);
+        assert_ne!(c_1.data, c_2.data);
+        assert_eq!(decrypt(&c_1, &params, &key).unwrap(), f);
+        assert_eq!(decrypt(&c_2, &params, &key).unwrap(), f);
+        // the permutation recovered from one encryption does not decrypt the
+        // other, and no permutation explains both
+        let (_, w_inv) = get_permutation_matrix(std::slice::from_ref(&f), std::slice::from_ref(&c_1.data)).unwrap();
+        assert_eq!(apply_permutation_matrix(&w_inv, &c_1.data), f);
+        assert_ne!(apply_permutation_matrix(&w_inv, &c_2.data), f);
+        assert!(get_permutation_matrix(&[f.clone(), f.clone()], &[c_1.data, c_2.data]).is_none());
+    }
+
+    #[test]
+    fn keys_per_level() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let other = SecretKey::new(0.5678, 3.99).unwrap();
+        let f = Array2::from_shape_fn((70, 45), |(i, j)| (i * 45 + j) as u16);
+        for params in [HcieParams::new(8, 8), HcieParams { sub_blocks: vec![(4, 4)], diffusion: Diffusion::Xor, ..HcieParams::new(8, 8) }] {
+            let keys = (0..params.levels()).map(|level| level_key(&key, level)).collect::<Vec<_>>();
+            let c = encrypt_with_keys(&f, &params, &keys).unwrap();
+            assert_eq!(decrypt_with_keys(&c, &params, &keys).unwrap(), f);
+            let mut wrong = keys.clone();
+            wrong[params.levels() - 1] = other;
+            assert_ne!(decrypt_with_keys(&c, &params, &wrong).unwrap(), f);
+            assert!(matches!(encrypt_with_keys(&f, &params, &[keys.clone(), vec![other]].concat()), Err(Error::InvalidParams(_))));
+        }
+    }
+
+    #[test]
+    fn level_keys_rotate_independently() {
+        let params = HcieParams::new(8, 8);
+        let (s_m, s_n) = (params.s_m, params.s_n);
+        let (m, n) = (64, 64);
+        let f = Array2::from_shape_fn((m, n), |(i, j)| (i * n + j) as u32);
+        // for every encrypted block, the plain block it comes from and where
+        // each of its pixels was inside that block
+        let layout = |keys: &[SecretKey]| {
+            let c = encrypt_seeded(&f, &params, keys, &[3; 16], None).unwrap();
+            let mut table = Vec::new();
+            let mut pixels = Vec::new();
+            for c_sub in c.data.exact_chunks((s_m, s_n)) {
+                let (i, j) = (c_sub[[0, 0]] as usize / n, c_sub[[0, 0]] as usize % n);
+                table.push(i / s_m * (n / s_n) + j / s_n);
+                pixels.push(c_sub.iter().map(|&v| (v as usize / n % s_m, v as usize % n % s_n)).collect::<Vec<_>>());
+            }
+            (table, pixels)
+        };
+        let keys = [SecretKey::new(0.1234, 3.97).unwrap(), SecretKey::new(0.4321, 3.98).unwrap()];
+        let other = SecretKey::new(0.5678, 3.99).unwrap();
+        let (table, pixels) = layout(&keys);
+        // a new key for the pixels keeps the order of the blocks
+        let (new_table, new_pixels) = layout(&[keys[0], other]);
+        assert_eq!(new_table, table);
+        assert_ne!(new_pixels, pixels);
+        // a new key for the order of the blocks keeps the permutation of
+        // the pixels at every position of the encrypted image
+        let (new_table, new_pixels) = layout(&[other, keys[1]]);
+        assert_ne!(new_table, table);
+        assert_eq!(new_pixels, pixels);
+    }
+
+    #[test]
+    fn permutation_modes() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let f = Array2::from_shape_fn((64, 64), |(i, j)| (i * 64 + j) as u32);
+        let chunks = |f: &Array2<u32>, size: (usize, usize)| f.exact_chunks(size).into_iter().map(|c| c.iter().copied().collect::<Vec<_>>()).collect::<Vec<_>>();
+        for sub_blocks in [vec![], vec![(4, 4)]] {
+            let smallest = *sub_blocks.last().unwrap_or(&(8, 8));
+            let plain = chunks(&f, smallest);
+            // only whole blocks are moved, at every level
+            let params = HcieParams { sub_blocks: sub_blocks.clone(), permutation: PermutationMode::Blocks, ..HcieParams::new(8, 8) };
+            let c = encrypt(&f, &params, &key).unwrap();
+            assert_ne!(c.data, f);
+            let mut moved = chunks(&c.data, smallest);
+            assert_ne!(moved, plain);
+            moved.sort();
+            assert_eq!(moved, plain);
+            assert_eq!(decrypt(&c, &params, &key).unwrap(), f);
+            // every pixel stays in its block
+            let params = HcieParams { sub_blocks, permutation: PermutationMode::Pixels, ..HcieParams::new(8, 8) };
+            let c = encrypt(&f, &params, &key).unwrap();
+            assert_ne!(c.data, f);
+            for (mut block, plain) in chunks(&c.data, smallest).into_iter().zip(&plain) {
+                block.sort();
+                assert_eq!(&block, plain);
+            }
+            assert_eq!(decrypt(&c, &params, &key).unwrap(), f);
+        }
+    }
+
+    #[test]
+    fn permutes_wordless_pixels() {
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        struct Pixel {
+            depth: i16,
+            label: char,
+        }
+
+        impl Substitute for Pixel {}
+
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let params = HcieParams::new(8, 8);
+        let f = Array2::from_shape_fn((40, 24), |(i, j)| Pixel { depth: i as i16 * 24 + j as i16 - 500, label: (b'a' + (i % 26) as u8) as char });
+        let c = encrypt(&f, &params, &key).unwrap();
+        assert_ne!(c.data, f);
+        assert_eq!(decrypt(&c, &params, &key).unwrap(), f);
+        let mut g = f.clone();
+        let header = encrypt_in_place(g.view_mut(), &params, &key).unwrap();
+        decrypt_in_place(g.view_mut(), &header, &params, &key).unwrap();
+        assert_eq!(g, f);
+        // their values can be neither diffused nor digested, unlike those
+        // of pixels with words
+        let depths = f.mapv(|p| p.depth);
+        for params in [HcieParams { diffusion: Diffusion::Xor, ..params.clone() }, HcieParams { plaintext_dependent: true, ..params.clone() }] {
+            assert!(matches!(encrypt(&f, &params, &key), Err(Error::InvalidParams(_))));
+            assert!(matches!(encrypt_in_place(f.clone().view_mut(), &params, &key), Err(Error::InvalidParams(_))));
+            let c = encrypt(&depths, &params, &key).unwrap();
+            assert_eq!(decrypt(&c, &params, &key).unwrap(), depths);
+        }
+    }
+
+    #[test]
+    fn parallel_map_keeps_order() {
+        for threads in [1, 2, 3, 8] {
+            THREADS.set(Some(threads));
+            let mut items = (0..100).collect::<Vec<usize>>();
+            let results = parallel_map(&mut items, |k, item| {
+                *item += 1;
+                (k, *item)
+            });
+            assert_eq!(results, (0..100).map(|k| (k, k + 1)).collect::<Vec<_>>());
+        }
+    }
+}
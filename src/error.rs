@@ -0,0 +1,38 @@
+use std::fmt;
+
+/// Errors returned by the fallible functions of this crate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// The [`HcieParams`](crate::HcieParams) are inconsistent.
+    InvalidParams(String),
+    /// The key would generate a non-chaotic bit sequence.
+    WeakKey(String),
+    /// The image cannot be processed with the given parameters.
+    InvalidDimensions(String),
+    /// The header of an encrypted image is malformed.
+    InvalidHeader(String),
+    /// A key file is malformed.
+    InvalidKeyFile(String),
+    /// A decrypted image does not match the digest recorded when it was
+    /// encrypted: the key or the parameters are wrong, or the encrypted
+    /// image was modified.
+    DigestMismatch(String),
+    /// A file could not be read or written.
+    Io(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidParams(msg) => write!(f, "invalid parameters: {}", msg),
+            Error::WeakKey(msg) => write!(f, "weak key: {}", msg),
+            Error::InvalidDimensions(msg) => write!(f, "invalid dimensions: {}", msg),
+            Error::InvalidHeader(msg) => write!(f, "invalid header: {}", msg),
+            Error::InvalidKeyFile(msg) => write!(f, "invalid key file: {}", msg),
+            Error::DigestMismatch(msg) => write!(f, "digest mismatch: {}", msg),
+            Error::Io(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,14 +1,24 @@
-use std::collections::{HashSet, BTreeMap, BTreeSet};
+use std::collections::HashSet;
 
 use ndarray::Array2;
-use sprs::CsMat;
 
-/// Returns a permutation matrix W such that `y = W * x`.
-/// 
-/// We assume that all x's and y's have the same dimensions m x n,
-/// and that every y is a permutation of x. In case this assumption
-/// does not hold, the function will panic.
-pub fn get_permutation_matrix(xs: &[Array2<u8>], ys: &[Array2<u8>]) -> (Array2::<(usize, usize)>, Array2::<(usize, usize)>) {
+/// A permutation of the pixels of an `m x n` image:
+/// the pixel at `(i, j)` is sent to `W(i, j)`.
+pub type PermutationMatrix = Array2<(usize, usize)>;
+
+/// Returns a permutation matrix W such that `y = W * x`, and its inverse.
+///
+/// All x's and y's must have the same dimensions m x n, and every y must be
+/// a permutation of x by the same W. Returns `None` otherwise, e.g. when
+/// the pairs were encrypted with different nonces, or when there are no pairs.
+pub fn get_permutation_matrix<T>(xs: &[Array2<T>], ys: &[Array2<T>]) -> Option<(PermutationMatrix, PermutationMatrix)>
+where
+    T: Copy + PartialEq,
+{
+    let dim = xs.first()?.dim();
+    if xs.len() != ys.len() || xs.iter().chain(ys).any(|f| f.dim() != dim) {
+        return None;
+    }
     let m = xs[0].shape()[0];
     let n = xs[0].shape()[1];
     let p = xs.len();
@@ -18,7 +28,7 @@ pub fn get_permutation_matrix(xs: &[Array2<u8>], ys: &[Array2<u8>]) -> (Array2::
 
     // we can use HashSet for every Lambda_k(l) to get O(1) lookup time
     // create lambdas as a closure (lazy evaluation)
-    let lambda = |k: usize, l: u8| {
+    let lambda = |k: usize, l: T| {
         (0..m).flat_map(|i| {
             (0..n).filter_map(move |j| {
                 if ys[k][(i,j)] == l {
@@ -42,7 +52,7 @@ pub fn get_permutation_matrix(xs: &[Array2<u8>], ys: &[Array2<u8>]) -> (Array2::
             ((i,j), w_ast_ij)
         })
     }).collect::<Vec<_>>();
-    w_ast.sort_by(|(_, w_0), (_, w_1)| w_0.len().cmp(&w_1.len()));
+    w_ast.sort_by_key(|(_, w_ast_ij)| w_ast_ij.len());
 
     // determine a unique-valued matrix W from W_ast
     // such that W(i,j) \in W_ast(i,j) for all i,j
@@ -53,12 +63,13 @@ pub fn get_permutation_matrix(xs: &[Array2<u8>], ys: &[Array2<u8>]) -> (Array2::
     // and then remove that possibility from all other W_ast(i',j') where (i',j') \neq (i,j)
     let mut w = Array2::<(usize, usize)>::from_elem((m, n), (0,0));
     let mut w_inv = Array2::<(usize, usize)>::from_elem((m, n), (0,0));
-    for mid in 1..w_ast.len() {
+    for mid in 0..w_ast.len() {
         let (prev, current_onwards) = w_ast.split_at_mut(mid);
         let ((i,j), w_ast_ij) = &current_onwards[0];
         let (i, j) = (*i, *j);
-        // pick first element of w_ast_ij
-        let idx = *w_ast_ij.iter().next().unwrap();
+        // pick first element of w_ast_ij; there is none if no single
+        // permutation maps every x to its y
+        let idx = *w_ast_ij.iter().next()?;
         w[(i, j)] = idx;
         w_inv[idx] = (i, j);
         // remove the current element from all other w_ast
@@ -71,14 +82,18 @@ pub fn get_permutation_matrix(xs: &[Array2<u8>], ys: &[Array2<u8>]) -> (Array2::
         }
     }
 
-    (w, w_inv)
+    Some((w, w_inv))
 }
 
 /// Applies a permutation matrix W to a matrix x.
-pub fn apply_permutation_matrix(w: &Array2::<(usize, usize)>, x: &Array2::<u8>) -> Array2::<u8> {
+pub fn apply_permutation_matrix<T>(w: &PermutationMatrix, x: &Array2::<T>) -> Array2::<T>
+where
+    T: Copy,
+{
     let m = x.shape()[0];
     let n = x.shape()[1];
-    let mut y = Array2::<u8>::zeros((m, n));
+    // every element of y is overwritten
+    let mut y = x.clone();
     for i in 0..m {
         for j in 0..n {
             let (i_prime, j_prime) = w[(i,j)];
@@ -87,3 +102,46 @@ pub fn apply_permutation_matrix(w: &Array2::<(usize, usize)>, x: &Array2::<u8>)
     }
     y
 }
+
+/// Checks whether `y` is a permutation of `x`.
+///
+/// When sorted, x and its permutation y must be equal.
+pub fn is_permutation<T>(x: &Array2<T>, y: &Array2<T>) -> bool
+where
+    T: Clone + Ord,
+{
+    // convert to Vec<T>
+    let mut x = x.iter().cloned().collect::<Vec<_>>();
+    let mut y = y.iter().cloned().collect::<Vec<_>>();
+    // sort
+    x.sort();
+    y.sort();
+    // compare
+    x == y
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovers_the_permutation() {
+        let x = Array2::from_shape_fn((4, 5), |(i, j)| (i * 5 + j) as u8);
+        let y = x.slice(ndarray::s![..;-1, ..;-1]).to_owned();
+        let (w, w_inv) = get_permutation_matrix(std::slice::from_ref(&x), std::slice::from_ref(&y)).unwrap();
+        assert_eq!(apply_permutation_matrix(&w, &x), y);
+        assert_eq!(apply_permutation_matrix(&w_inv, &y), x);
+    }
+
+    #[test]
+    fn rejects_inconsistent_pairs() {
+        let x = Array2::from_shape_fn((2, 2), |(i, j)| (i * 2 + j) as u8);
+        let y_1 = Array2::from_shape_vec((2, 2), vec![1, 0, 2, 3]).unwrap();
+        let y_2 = Array2::from_shape_vec((2, 2), vec![0, 1, 3, 2]).unwrap();
+        assert!(get_permutation_matrix(&[x.clone(), x.clone()], &[y_1, y_2]).is_none());
+        let z = Array2::from_shape_fn((1, 4), |(_, j)| j as u8);
+        assert!(is_permutation(&x, &z));
+        assert!(get_permutation_matrix(std::slice::from_ref(&x), std::slice::from_ref(&z)).is_none());
+        assert!(get_permutation_matrix::<u8>(&[], &[]).is_none());
+    }
+}
@@ -1,25 +1,45 @@
-use image::GrayImage;
+use image::{GrayImage, ImageBuffer, Luma, Pixel, Primitive, RgbImage, RgbaImage};
 use ndarray::Array2;
 
-/// Converts GrayScale image to array of pixels
-pub fn img_to_array(img: &GrayImage) -> Array2<u8> {
+/// Converts GrayScale image (8 or 16 bits) to array of pixels
+pub fn img_to_array<S>(img: &ImageBuffer<Luma<S>, Vec<S>>) -> Array2<S>
+where
+    S: Primitive,
+{
     let (width, height) = img.dimensions();
-    let mut array = Array2::<u8>::zeros((width as usize, height as usize));
-    for (x, y, pixel) in img.enumerate_pixels() {
-        array[[x as usize, y as usize]] = pixel[0];
-    }
-    array
+    Array2::from_shape_fn((width as usize, height as usize), |(x, y)| img.get_pixel(x as u32, y as u32)[0])
 }
 
-pub fn array_to_img(array: &Array2<u8>) -> GrayImage {
+pub fn array_to_img<S>(array: &Array2<S>) -> ImageBuffer<Luma<S>, Vec<S>>
+where
+    S: Primitive,
+{
     let (width, height) = array.dim();
-    let mut img = GrayImage::new(width as u32, height as u32);
+    let mut img = ImageBuffer::new(width as u32, height as u32);
     for (x, y, pixel) in img.enumerate_pixels_mut() {
-        *pixel = image::Luma([array[[x as usize, y as usize]]]);
+        *pixel = Luma([array[[x as usize, y as usize]]]);
     }
     img
 }
 
+/// Converts an image to an array of whole pixels, so that
+/// all the channels of a pixel are moved together
+pub fn img_to_pixels<P>(img: &ImageBuffer<P, Vec<P::Subpixel>>) -> Array2<P>
+where
+    P: Pixel,
+{
+    let (width, height) = img.dimensions();
+    Array2::from_shape_fn((width as usize, height as usize), |(x, y)| *img.get_pixel(x as u32, y as u32))
+}
+
+pub fn pixels_to_img<P>(array: &Array2<P>) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel,
+{
+    let (width, height) = array.dim();
+    ImageBuffer::from_fn(width as u32, height as u32, |x, y| array[[x as usize, y as usize]])
+}
+
 /// Open an image from a filepath
 pub fn open_grayscale(path: &str) -> GrayImage {
     image::open(path).unwrap().to_luma8()
@@ -28,4 +48,14 @@ pub fn open_grayscale(path: &str) -> GrayImage {
 /// Save an image to a filepath
 pub fn save_grayscale(img: &GrayImage, path: &str) {
     img.save(path).unwrap();
-}
\ No newline at end of file
+}
+
+/// Open an RGB image from a filepath
+pub fn open_rgb(path: &str) -> RgbImage {
+    image::open(path).unwrap().to_rgb8()
+}
+
+/// Open an RGBA image from a filepath
+pub fn open_rgba(path: &str) -> RgbaImage {
+    image::open(path).unwrap().to_rgba8()
+}
@@ -0,0 +1,161 @@
+//! Derivation of keys from passphrases and nonces.
+//!
+//! The passphrase is stretched with PBKDF2-HMAC-SHA-256 (RFC 8018), and the
+//! resulting bytes are mapped to a [`SecretKey`] and to the rotation
+//! parameters of [`HcieParams`]. The key of every encryption is derived
+//! from the secret key, a nonce and optionally a digest of the image
+//! with HMAC-SHA-256.
+
+use ndarray::{ArrayBase, Data, Ix2};
+use serde::{Deserialize, Serialize};
+
+use crate::{ciphertext::Digest, diffusion::Substitute, keyfile::KeyFile, logistic::SecretKey, params::HcieParams, sha256::{hmac_sha256, sha256}};
+
+/// Default number of PBKDF2 iterations.
+pub const DEFAULT_ITERATIONS: u32 = 100_000;
+
+/// Public inputs of the derivation, besides the passphrase.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KdfParams {
+    /// The salt, used as UTF-8 bytes.
+    pub salt: String,
+    pub iterations: u32,
+}
+
+/// PBKDF2-HMAC-SHA-256: fills `out` with key material derived from
+/// `password` and `salt`.
+pub fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32, out: &mut [u8]) {
+    assert!(iterations > 0);
+    for (i, chunk) in out.chunks_mut(32).enumerate() {
+        let mut salt_i = salt.to_vec();
+        salt_i.extend_from_slice(&(i as u32 + 1).to_be_bytes());
+        let mut u = hmac_sha256(password, &salt_i);
+        let mut t = u;
+        for _ in 1..iterations {
+            u = hmac_sha256(password, &u);
+            for (t, u) in t.iter_mut().zip(u.iter()) {
+                *t ^= u;
+            }
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+/// Derives a secret key from a passphrase and a salt.
+///
+/// `x_0` lies in `[0.01, 0.99)` and `mu` in `[3.9, 4)`; the key always passes
+/// [`SecretKey::validate`].
+pub fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> SecretKey {
+    let mut bytes = [0u8; 16];
+    pbkdf2_hmac_sha256(passphrase.as_bytes(), salt, iterations, &mut bytes);
+    key_from_bytes(&bytes)
+}
+
+/// Derives a key file from a passphrase and a salt. The block size and the
+/// number of rounds are taken from `params`; `alpha`, `beta` and `gamma`
+/// (each in `1..=8`) are derived with the key.
+pub fn derive_key_file(passphrase: &str, salt: &[u8], iterations: u32, params: &HcieParams) -> KeyFile {
+    let mut bytes = [0u8; 19];
+    pbkdf2_hmac_sha256(passphrase.as_bytes(), salt, iterations, &mut bytes);
+    let params = HcieParams {
+        alpha: 1 + (bytes[16] % 8) as usize,
+        beta: 1 + (bytes[17] % 8) as usize,
+        gamma: 1 + (bytes[18] % 8) as usize,
+        ..params.clone()
+    };
+    KeyFile::new(key_from_bytes(&bytes[..16]), params)
+}
+
+/// Derives the key of an encryption with `nonce` from the secret key.
+///
+/// The new key is HMAC-SHA-256 of the nonce under the secret key, mapped to
+/// a key as in [`derive_key`], so that it reveals nothing about the secret
+/// key nor about the keys of other nonces.
+pub fn nonce_key(key: &SecretKey, nonce: &[u8]) -> SecretKey {
+    key_from_bytes(&hmac_sha256(&secret_bytes(key), nonce)[..16])
+}
+
+/// Derives the subkey of the `level`-th level of the hierarchy from the
+/// secret key, as [`nonce_key`] does for nonces.
+///
+/// The subkeys of different levels are unrelated, so the keystream of one
+/// level reveals nothing about those of the others.
+pub fn level_key(key: &SecretKey, level: usize) -> SecretKey {
+    nonce_key(key, &[b"level".as_slice(), &(level as u64).to_be_bytes()].concat())
+}
+
+/// Derives the subkey of the `channel`-th channel of a colour image
+/// encrypted with [`ColorMode::PerChannel`](crate::ColorMode::PerChannel),
+/// as [`level_key`] does for levels.
+pub fn channel_key(key: &SecretKey, channel: usize) -> SecretKey {
+    nonce_key(key, &[b"channel".as_slice(), &(channel as u64).to_be_bytes()].concat())
+}
+
+/// Keyed digest of an image: HMAC-SHA-256, under the secret key, of its
+/// dimensions and of the words of its pixels.
+///
+/// Without the key, the digest cannot be computed, so it does not allow
+/// checking a guess of the plain image.
+pub fn image_digest<T, S>(key: &SecretKey, f: &ArrayBase<S, Ix2>) -> Digest
+where
+    T: Substitute,
+    S: Data<Elem = T>,
+{
+    let (m, n) = f.dim();
+    let mut data = (m as u64).to_be_bytes().to_vec();
+    data.extend_from_slice(&(n as u64).to_be_bytes());
+    for pixel in f.iter() {
+        pixel.zip_words(*pixel, |x, _, bits| {
+            data.extend_from_slice(&x.to_be_bytes()[8 - bits as usize / 8..]);
+            x
+        });
+    }
+    hmac_sha256(&secret_bytes(key), &data)
+}
+
+fn secret_bytes(key: &SecretKey) -> Vec<u8> {
+    let mut secret = key.x_0.to_be_bytes().to_vec();
+    secret.extend_from_slice(&key.mu.to_be_bytes());
+    secret
+}
+
+/// Maps 16 bytes to a key: the first 8 give `x_0` and the next 8 give `mu`.
+///
+/// Weak keys (e.g. with `mu` in a periodic window) are rejected, and new
+/// bytes are drawn by hashing the previous ones until the key is valid.
+fn key_from_bytes(bytes: &[u8]) -> SecretKey {
+    let unit = |b: &[u8]| (u64::from_be_bytes(b.try_into().unwrap()) >> 11) as f64 / (1u64 << 53) as f64;
+    let mut bytes = bytes.to_vec();
+    loop {
+        let x_0 = 0.01 + 0.98 * unit(&bytes[0..8]);
+        let mu = 3.9 + 0.1 * unit(&bytes[8..16]);
+        if let Ok(key) = SecretKey::new(x_0, mu) {
+            return key;
+        }
+        bytes = sha256(&bytes)[..16].to_vec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pbkdf2_known_answers() {
+        // RFC 7914, section 11, and the usual PBKDF2-HMAC-SHA-256 vectors
+        let vectors: [(&[u8], &[u8], u32, &str); 6] = [
+            (b"passwd", b"salt", 1, "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"),
+            (b"Password", b"NaCl", 80000, "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56a1d425a1225833549adb841b51c9b3176a272bdebba1d078478f62b397f33c8d"),
+            (b"password", b"salt", 1, "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"),
+            (b"password", b"salt", 2, "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"),
+            (b"password", b"salt", 4096, "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"),
+            (b"passwordPASSWORDpassword", b"saltSALTsaltSALTsaltSALTsaltSALTsalt", 4096, "348c89dbcbd32b2f32d814b8116e84cf2b17347ebc1800181c4e2a1fb8dd53e1c635518c7dac47e9"),
+        ];
+        for (password, salt, iterations, expected) in vectors {
+            let mut out = vec![0; expected.len() / 2];
+            pbkdf2_hmac_sha256(password, salt, iterations, &mut out);
+            assert_eq!(out.iter().map(|b| format!("{:02x}", b)).collect::<String>(), expected);
+        }
+    }
+}
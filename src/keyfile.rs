@@ -0,0 +1,79 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Error, logistic::SecretKey, params::HcieParams};
+
+/// Version of the key file format.
+///
+/// Version 2 added the parameters after the rotations (`map`, `extraction`,
+/// `diffusion`, ...); key files of version 1 have none of them, and are
+/// still read, with their defaults. Unknown fields are rejected, so that a
+/// key file is never used without a parameter it sets.
+pub const KEY_FILE_VERSION: u32 = 2;
+
+/// Everything needed to encrypt and decrypt: the secret key and the
+/// parameters of the scheme.
+///
+/// Key files are JSON documents. The floats are written with enough digits
+/// to be read back bit for bit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyFile {
+    pub version: u32,
+    pub key: SecretKey,
+    pub params: HcieParams,
+}
+
+impl KeyFile {
+    pub fn new(key: SecretKey, params: HcieParams) -> Self {
+        Self { version: KEY_FILE_VERSION, key, params }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, Error> {
+        let key_file: Self = serde_json::from_str(json).map_err(|e| Error::InvalidKeyFile(e.to_string()))?;
+        if !(1..=KEY_FILE_VERSION).contains(&key_file.version) {
+            return Err(Error::InvalidKeyFile(format!("unsupported version {}", key_file.version)));
+        }
+        key_file.key.validate()?;
+        key_file.params.validate()?;
+        Ok(key_file)
+    }
+
+    /// Writes the key file to `path`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        std::fs::write(path, self.to_json()).map_err(|e| Error::Io(e.to_string()))
+    }
+
+    /// Reads a key file from `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let json = std::fs::read_to_string(path).map_err(|e| Error::Io(e.to_string()))?;
+        Self::from_json(&json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Diffusion, MapKind};
+
+    #[test]
+    fn key_file_versions() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let key_file = KeyFile::new(key, HcieParams { map: MapKind::Tent, diffusion: Diffusion::Xor, ..HcieParams::new(16, 8) });
+        assert_eq!(KeyFile::from_json(&key_file.to_json()).unwrap(), key_file);
+        // key files written before the pluggable maps only have the rotations
+        let params = r#"{"s_m": 16, "s_n": 8, "n_iter": 2, "alpha": 4, "beta": 2, "gamma": 1}"#;
+        let json = |version: u32, params: &str| format!(r#"{{"version": {}, "key": {{"x_0": 0.1234, "mu": 3.97}}, "params": {}}}"#, version, params);
+        assert_eq!(KeyFile::from_json(&json(1, params)).unwrap().params, HcieParams::new(16, 8));
+        for version in [0, KEY_FILE_VERSION + 1] {
+            assert!(KeyFile::from_json(&json(version, params)).is_err());
+        }
+        let unknown = params.replace("\"gamma\": 1", "\"gamma\": 1, \"rounds\": 3");
+        assert!(matches!(KeyFile::from_json(&json(2, &unknown)), Err(Error::InvalidKeyFile(_))));
+    }
+}
@@ -0,0 +1,199 @@
+//! Lazy bit sequence generated from the orbit of a chaotic map.
+
+use crate::chaotic::{BitExtraction, ChaoticMap};
+
+/// The bit sequence of a chaotic map, generated as it is consumed.
+///
+/// Bits are produced by iterating `map` and extracting bits from every
+/// iterate according to a [`BitExtraction`], so memory use does not depend
+/// on the number of bits read. [`position`](Self::position) counts the bits
+/// read so far; the keystream can only move forward, since the map cannot be
+/// iterated backwards. As an [`Iterator`], it never ends.
+pub struct Keystream<'a, M>
+where
+    M: ChaoticMap + ?Sized,
+{
+    map: &'a mut M,
+    extraction: BitExtraction,
+    position: usize,
+    // bits of the last iterate not read yet, the next one at bit pending - 1
+    word: u64,
+    pending: u32,
+}
+
+impl<'a, M> Keystream<'a, M>
+where
+    M: ChaoticMap + ?Sized,
+{
+    /// Starts the keystream of `map`, which should be in its initial state,
+    /// after skipping the transient of `extraction`.
+    pub fn new(map: &'a mut M, extraction: BitExtraction) -> Self {
+        for _ in 0..extraction.skip {
+            map.next();
+        }
+        Self { map, extraction, position: 0, word: 0, pending: 0 }
+    }
+
+    /// Number of bits read so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Discards bits until [`position`](Self::position) is `position`.
+    ///
+    /// Panics if `position` is behind the current position.
+    pub fn seek(&mut self, position: usize) {
+        assert!(position >= self.position, "cannot seek the keystream backwards from bit {} to bit {}", self.position, position);
+        let mut skip = position - self.position;
+        let pending = (self.pending as usize).min(skip);
+        self.pending -= pending as u32;
+        skip -= pending;
+        // whole iterates are skipped without extracting their bits
+        let bits = self.extraction.bits_per_iterate() as usize;
+        for _ in 0..skip / bits {
+            self.map.next();
+        }
+        self.position = position - skip % bits;
+        for _ in 0..skip % bits {
+            self.bit();
+        }
+    }
+
+    /// Fills `buf` with the next bits.
+    pub fn read(&mut self, buf: &mut [u8]) {
+        for b in buf.iter_mut() {
+            *b = self.bit();
+        }
+    }
+
+    /// Packs the next `n` bits into `buf`, 64 per word, the first one in the
+    /// most significant bit of the first word (see [`PackedBits`]).
+    ///
+    /// Panics if `buf` holds less than `n` bits.
+    pub fn read_packed(&mut self, buf: &mut [u64], n: usize) {
+        buf[..n.div_ceil(64)].fill(0);
+        let mut i = 0;
+        while i < n {
+            if self.pending == 0 {
+                self.word = self.extraction.extract(self.map);
+                self.pending = self.extraction.bits_per_iterate();
+            }
+            // as many bits of the iterate as fit in the current word
+            let k = (self.pending as usize).min(64 - i % 64).min(n - i);
+            let bits = (self.word >> (self.pending as usize - k)) & (u64::MAX >> (64 - k));
+            buf[i / 64] |= bits << (64 - i % 64 - k);
+            self.pending -= k as u32;
+            self.position += k;
+            i += k;
+        }
+    }
+
+    /// The next `bits` bits (at most 64), most significant first.
+    pub fn word(&mut self, bits: u32) -> u64 {
+        (0..bits).fold(0, |w, _| (w << 1) | self.bit() as u64)
+    }
+
+    /// The next bit.
+    pub fn bit(&mut self) -> u8 {
+        if self.pending == 0 {
+            self.word = self.extraction.extract(self.map);
+            self.pending = self.extraction.bits_per_iterate();
+        }
+        self.pending -= 1;
+        self.position += 1;
+        ((self.word >> self.pending) & 1) as u8
+    }
+}
+
+impl<M> Iterator for Keystream<'_, M>
+where
+    M: ChaoticMap + ?Sized,
+{
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        Some(self.bit())
+    }
+}
+
+/// A sequence of bits, such as the bits read from a [`Keystream`].
+pub trait Bits {
+    /// The `i`-th bit, 0 or 1.
+    fn bit(&self, i: usize) -> u8;
+}
+
+impl Bits for [u8] {
+    fn bit(&self, i: usize) -> u8 {
+        self[i]
+    }
+}
+
+/// Bits packed by [`Keystream::read_packed`], using a bit of memory per
+/// bit of the keystream instead of a byte.
+#[derive(Debug, Clone, Copy)]
+pub struct PackedBits<'a> {
+    words: &'a [u64],
+    start: usize,
+    len: usize,
+}
+
+impl<'a> PackedBits<'a> {
+    /// The first `len` bits of `words`.
+    pub fn new(words: &'a [u64], len: usize) -> Self {
+        assert!(len <= words.len() * 64, "{} bits do not fit in {} words", len, words.len());
+        Self { words, start: 0, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The first `n` bits, and the remaining ones.
+    pub fn split_at(self, n: usize) -> (Self, Self) {
+        assert!(n <= self.len, "cannot take {} bits out of {}", n, self.len);
+        (Self { len: n, ..self }, Self { start: self.start + n, len: self.len - n, ..self })
+    }
+}
+
+impl Bits for PackedBits<'_> {
+    fn bit(&self, i: usize) -> u8 {
+        assert!(i < self.len, "bit {} out of {}", i, self.len);
+        let i = self.start + i;
+        ((self.words[i / 64] >> (63 - i % 64)) & 1) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chaotic::{ExtractionMethod, MapKind}, logistic::SecretKey};
+
+    #[test]
+    fn packed_bits_match_read() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        for (method, bits) in [(ExtractionMethod::Fraction, 8), (ExtractionMethod::Fraction, 31), (ExtractionMethod::Threshold, 1), (ExtractionMethod::Mantissa, 52)] {
+            let extraction = BitExtraction { method, bits, skip: 3 };
+            let mut map = MapKind::Logistic.build(&key).unwrap();
+            let mut keystream = Keystream::new(&mut map, extraction);
+            let mut expected = vec![0; 1000];
+            keystream.read(&mut expected);
+            let mut map = MapKind::Logistic.build(&key).unwrap();
+            let mut keystream = Keystream::new(&mut map, extraction);
+            let mut words = vec![0; 16];
+            let mut read = 0;
+            // reads of every length, across words and iterates
+            for n in [0, 1, 7, 64, 65, 100, 3, 200] {
+                keystream.read_packed(&mut words, n);
+                let (head, tail) = PackedBits::new(&words, n).split_at(n / 3);
+                let bits = (0..head.len()).map(|i| head.bit(i)).chain((0..tail.len()).map(|i| tail.bit(i))).collect::<Vec<_>>();
+                assert_eq!(bits, &expected[read..read + n]);
+                read += n;
+                assert_eq!(keystream.position(), read);
+            }
+        }
+    }
+}
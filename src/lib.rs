@@ -0,0 +1,74 @@
+//! Hierarchical Chaotic Image Encryption (HCIE).
+//!
+//! Implementation of the image encryption scheme proposed by
+//! Yen, J-C., and J-I. Guo (2000). An image of size `m x n` is split
+//! into blocks of size `s_m x s_n`; the order of the blocks and the
+//! pixels inside every block are then permuted by row, column and
+//! diagonal rotations driven by a bit sequence obtained from the
+//! logistic map. Blocks can also be split into smaller blocks, for a
+//! hierarchy of any depth ([`HcieParams::sub_blocks`]).
+//!
+//! The crate is organized as follows:
+//!
+//! - [`encrypt`]: the full cipher ([`encrypt()`] and [`decrypt()`], or [`decrypt_region`]
+//!   for a part of the image, and [`encrypt_in_place`] and [`decrypt_in_place`] for
+//!   images too large to be copied);
+//! - [`ciphertext`]: the encrypted image and its public [`Header`], which holds
+//!   the [`Nonce`] of the encryption and, optionally, the [`Digest`] of the image;
+//! - [`color`]: encryption of RGB and RGBA images ([`ColorMode`]);
+//! - [`params`]: the parameters of the scheme ([`HcieParams`]), shared by both;
+//! - [`plan`]: the permutation of [`encrypt()`] compiled into a table of indices,
+//!   to encrypt many images of the same size quickly ([`PermutationPlan`]);
+//! - [`diffusion`]: the optional substitution of the pixel values ([`Diffusion`]);
+//! - [`sub_hcie`]: the permutation of a single `s_m x s_n` block ([`SubHCIE`]);
+//! - [`kdf`]: derivation of keys from passphrases, nonces and image digests, built on [`sha256`];
+//! - [`keyfile`]: the key file format ([`KeyFile`]);
+//! - [`logistic`]: the secret key ([`SecretKey`]) and the logistic map, in floating
+//!   point, fixed point and arbitrary precision (built on [`bigfixed`]);
+//! - [`chaotic`]: the [`ChaoticMap`] trait, implemented by the logistic map and
+//!   other maps that can replace it ([`MapKind`]), and the extraction of the
+//!   bit sequence from their orbits ([`BitExtraction`]);
+//! - [`keystream`]: the bit sequence, generated lazily as it is consumed ([`Keystream`]);
+//! - [`rotate`]: the elementary row, column and diagonal rotations;
+//! - [`get_permutation_matrix`]: the known-plaintext attack, which recovers
+//!   the permutation from pairs of plain and encrypted images;
+//! - [`img_array`] and [`mean`]: helpers to convert images to arrays and back.
+//!
+//! Fallible functions return the crate's [`Error`].
+
+pub mod bigfixed;
+pub mod chaotic;
+pub mod ciphertext;
+pub mod color;
+pub mod diffusion;
+pub mod encrypt;
+pub mod error;
+pub mod get_permutation_matrix;
+pub mod img_array;
+pub mod kdf;
+pub mod keyfile;
+pub mod keystream;
+pub mod logistic;
+pub mod mean;
+pub mod params;
+pub mod plan;
+pub mod rotate;
+pub mod sha256;
+pub mod sub_hcie;
+
+pub use chaotic::{BitExtraction, ChaoticMap, ExtractionMethod, MapKind};
+pub use ciphertext::{Ciphertext, Digest, Header, Nonce};
+pub use color::{decrypt_color, decrypt_rgb, decrypt_rgba, encrypt_color, encrypt_rgb, encrypt_rgba, ColorMode};
+pub use diffusion::{Diffusion, Substitute};
+pub use encrypt::{decrypt, decrypt_in_place, decrypt_region, decrypt_with, decrypt_with_keys, decrypt_with_maps, encrypt, encrypt_in_place, encrypt_with, encrypt_with_keys, encrypt_with_maps, encrypt_with_nonce};
+pub use error::Error;
+pub use get_permutation_matrix::{
+    apply_permutation_matrix, get_permutation_matrix, is_permutation, PermutationMatrix,
+};
+pub use kdf::{derive_key, derive_key_file, image_digest};
+pub use keyfile::KeyFile;
+pub use keystream::Keystream;
+pub use logistic::SecretKey;
+pub use params::{HcieParams, LevelKeys, PermutationMode};
+pub use plan::PermutationPlan;
+pub use sub_hcie::{Operation, SubHCIE};
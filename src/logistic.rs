@@ -1,15 +1,218 @@
+use serde::{Deserialize, Serialize};
 
+use crate::{bigfixed::BigFixed, chaotic::{bitsequence, check_aperiodic, ChaoticMap, WARM_UP}, error::Error};
 
-// Secret key: (x_0, mu)
-// function: f(x) = mu * x * (1 - x)
+/// Smallest accepted `mu`: the onset of chaos of the logistic map.
+pub const MU_MIN: f64 = 3.569_945_672;
+
+// the Lyapunov exponent of the orbit of every new key, estimated while it
+// is checked for cycles, must be at least MIN_LYAPUNOV
+const MIN_LYAPUNOV: f64 = 0.05;
+
+/// Secret key: (x_0, mu)
+///
+/// `x_0` is the initial condition and `mu` the parameter of the
+/// logistic map f(x) = mu * x * (1 - x)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct SecretKey {
     pub x_0: f64,
     pub mu: f64,
 }
 
 impl SecretKey {
-    pub fn new(x_0: f64, mu: f64) -> Self {
-        Self { x_0, mu }
+    /// Creates a key, rejecting weak keys (see [`SecretKey::validate`]).
+    pub fn new(x_0: f64, mu: f64) -> Result<Self, Error> {
+        let key = Self { x_0, mu };
+        key.validate()?;
+        Ok(key)
+    }
+
+    /// Checks that the key generates a chaotic orbit.
+    ///
+    /// `x_0` must be in (0, 1) and `mu` in `[MU_MIN, 4]`. The orbit is then
+    /// iterated for a while: it must not leave (0, 1) (e.g. `x_0 = 0.5` with
+    /// `mu = 4` reaches 1 and then stays at 0), fall into a fixed point or a
+    /// short cycle (as in the periodic windows of `mu`), or have a Lyapunov
+    /// exponent close to 0. Any of these would turn the bit sequence into a
+    /// constant or periodic one, and the cipher into a fixed permutation.
+    pub fn validate(&self) -> Result<(), Error> {
+        let Self { x_0, mu } = *self;
+        if !(x_0 > 0.0 && x_0 < 1.0) {
+            return Err(Error::WeakKey(format!("x_0 = {} is not in (0, 1)", x_0)));
+        }
+        if !(MU_MIN..=4.0).contains(&mu) {
+            return Err(Error::WeakKey(format!("mu = {} is not in [{}, 4], where the logistic map is chaotic", mu, MU_MIN)));
+        }
+        let mut x = x_0;
+        let mut lyapunov = 0.0;
+        check_aperiodic("the orbit", |k| {
+            x = mu * x * (1.0 - x);
+            if !(x > 0.0 && x < 1.0) {
+                return Err(Error::WeakKey(format!("the orbit leaves (0, 1) after {} iterations (x = {}) and collapses", k + 1, x)));
+            }
+            lyapunov += (mu * (1.0 - 2.0 * x)).abs().ln();
+            Ok(x)
+        })?;
+        let lyapunov = lyapunov / WARM_UP as f64;
+        if lyapunov < MIN_LYAPUNOV {
+            return Err(Error::WeakKey(format!("the orbit is not chaotic enough (Lyapunov exponent {:.3} < {})", lyapunov, MIN_LYAPUNOV)));
+        }
+        Ok(())
+    }
+}
+
+/// The logistic map f(x) = mu * x * (1 - x), started at `x_0`.
+pub struct LogisticMap {
+    pub x: f64,
+    pub mu: f64,
+}
+
+impl LogisticMap {
+    pub fn new(key: &SecretKey) -> Self {
+        Self { x: key.x_0, mu: key.mu }
+    }
+}
+
+impl ChaoticMap for LogisticMap {
+    fn next(&mut self) -> f64 {
+        self.x = self.mu * self.x * (1.0 - self.x);
+        self.x
+    }
+}
+
+/// The logistic map in fixed-point arithmetic.
+///
+/// Floating point results can change with the target, the compiler flags
+/// or FMA contraction, and a single different bit makes the ciphertext
+/// undecryptable. This map only uses integer arithmetic, so its bit
+/// sequence is the same everywhere. It is specified as follows (`>>` is a
+/// logical shift, and products are computed exactly, e.g. in `u128`):
+///
+/// - the state `x` is a 64-bit unsigned integer, representing `x / 2^64`;
+/// - `m`, the 64-bit unsigned integer representing `mu` with 61 fractional
+///   bits, is `floor(mu * 2^61)`, and the initial state is
+///   `floor(x_0 * 2^64)` (both conversions are exact in IEEE 754);
+/// - each iteration computes `t = (x * (2^64 - x)) >> 64`, which is
+///   `x (1 - x)` with 64 fractional bits, and `x = min((m * t) >> 61, 2^64 - 1)`;
+/// - the bits of an iteration are the 8 (by default, see
+///   [`BitExtraction`](crate::chaotic::BitExtraction)) most significant bits
+///   of `x`, most significant first.
+///
+/// Test vectors, checked against an independent implementation (and by the
+/// tests of this module):
+///
+/// | `x_0`  | `mu`   | `m`                  | `x` after 1, 2 and 1000 iterations                           | first 16 bytes                     |
+/// |--------|--------|----------------------|--------------------------------------------------------------|------------------------------------|
+/// | 0.1    | 3.9999 | `0x7fff2e48e8a71c00` | `0x5c285ec3dab5c383`, `0xebebce9a5e17faec`, `0xfffb0f36c75b0e9f` | `5ceb4ad295f81c66f52887ff030d33a4` |
+/// | 0.5    | 3.99   | `0x7fae147ae147b000` | `0xff5c28f5c28f6000`, `0x028c165907d9031f`, `0xc2d14dd4346b42f9` | `ff020a2682ff030b2d94f91a5fef3dbb` |
+/// | 0.1234 | 3.97   | `0x7f0a3d70a3d70c00` | `0x6df01497c00f4a9e`, `0xf9054c0bdf6ee47b`, `0x1a1cbfe7c5fc48a6` | `6df91a5fed42c3b6cf9cf137abe06ef9` |
+pub struct FixedLogisticMap {
+    pub x: u64,
+    pub m: u64,
+}
+
+impl FixedLogisticMap {
+    pub fn new(key: &SecretKey) -> Self {
+        // multiplying by a power of 2 is exact, and `as` truncates
+        Self { x: (key.x_0 * 2f64.powi(64)) as u64, m: (key.mu * 2f64.powi(61)) as u64 }
+    }
+
+    /// Iterates the map once and returns the new state.
+    pub fn step(&mut self) -> u64 {
+        let x = self.x as u128;
+        let t = (x * ((1 << 64) - x)) >> 64;
+        self.x = ((self.m as u128 * t) >> 61).min(u64::MAX as u128) as u64;
+        self.x
+    }
+}
+
+impl ChaoticMap for FixedLogisticMap {
+    fn next(&mut self) -> f64 {
+        self.step() as f64 / 2f64.powi(64)
+    }
+
+    fn next_bits(&mut self, bits: u32) -> u64 {
+        self.step() >> (64 - bits)
+    }
+}
+
+/// Default precision, in bits, of [`BigLogisticMap`] when built from a [`SecretKey`].
+pub const BIG_PRECISION: usize = 256;
+
+/// The logistic map in arbitrary-precision arithmetic.
+///
+/// With `f64`, rounding errors double at every iteration (the Lyapunov
+/// exponent of the map is close to `ln 2` for `mu` near 4), so the computed
+/// orbit follows the true one for only about 50 iterations, and initial
+/// conditions closer than about `1e-16` give the same bit sequence. With
+/// `precision` bits, these become about `precision` iterations and
+/// `2^-precision`. No precision follows the true orbit over a whole bit
+/// sequence, but it sets how many bits of the key the sequence depends on,
+/// i.e. the effective key space.
+///
+/// The state is a [`BigFixed`] and every iteration computes
+/// `t = x (1 - x)` and `x = mu t`, truncating both products.
+#[derive(Debug, Clone)]
+pub struct BigLogisticMap {
+    pub x: BigFixed,
+    /// Integer part of `mu`.
+    pub mu_int: u64,
+    /// Fractional part of `mu`.
+    pub mu_frac: BigFixed,
+}
+
+impl BigLogisticMap {
+    /// Starts the map from `key`, whose `f64` values are converted exactly
+    /// if `precision` is large enough (see [`BigFixed::from_f64`]). As with
+    /// [`from_decimal`](Self::from_decimal), the key is not checked for
+    /// weakness beyond `x_0` in (0, 1) and `mu` in (0, 4].
+    pub fn new(key: &SecretKey, precision: usize) -> Result<Self, Error> {
+        let SecretKey { x_0, mu } = *key;
+        if !(x_0 > 0.0 && x_0 < 1.0) {
+            return Err(Error::WeakKey(format!("x_0 = {} is not in (0, 1)", x_0)));
+        }
+        if !(mu > 0.0 && mu <= 4.0) {
+            return Err(Error::WeakKey(format!("mu = {} is not in (0, 4]", mu)));
+        }
+        Ok(Self {
+            x: BigFixed::from_f64(x_0, precision),
+            mu_int: mu.trunc() as u64,
+            mu_frac: BigFixed::from_f64(mu.fract(), precision),
+        })
+    }
+
+    /// Starts the map from `x_0` and `mu` written in decimal, with more
+    /// digits than an `f64` holds. The key is not checked for weakness
+    /// beyond `x_0` in (0, 1) and `mu` in (0, 4].
+    pub fn from_decimal(x_0: &str, mu: &str, precision: usize) -> Result<Self, Error> {
+        let (x_int, x) = BigFixed::parse_decimal(x_0, precision)?;
+        let (mu_int, mu_frac) = BigFixed::parse_decimal(mu, precision)?;
+        if x_int != 0 || x.is_zero() {
+            return Err(Error::WeakKey(format!("x_0 = {} is not in (0, 1)", x_0)));
+        }
+        if mu_int > 4 || (mu_int == 4 && !mu_frac.is_zero()) || (mu_int == 0 && mu_frac.is_zero()) {
+            return Err(Error::WeakKey(format!("mu = {} is not in (0, 4]", mu)));
+        }
+        Ok(Self { x, mu_int, mu_frac })
+    }
+
+    /// Iterates the map once and returns the new state.
+    pub fn step(&mut self) -> &BigFixed {
+        let t = self.x.mul(&self.x.complement());
+        // mu t < 1, except for mu = 4 and x = 1/2
+        self.x = t.mul_small_add(self.mu_int, &self.mu_frac.mul(&t)).unwrap_or_else(|| BigFixed::zero(t.precision()).complement());
+        &self.x
+    }
+}
+
+impl ChaoticMap for BigLogisticMap {
+    fn next(&mut self) -> f64 {
+        self.step().to_f64()
+    }
+
+    fn next_bits(&mut self, bits: u32) -> u64 {
+        self.step().top_bits(bits)
     }
 }
 
@@ -18,23 +221,71 @@ impl SecretKey {
 /// We take the floating point values with finite binary precision
 /// and convert them to a binary sequence
 pub fn logistic_bitsequence(key: &SecretKey, n_iter: usize) -> Vec<u8> {
-    let mut x = key.x_0;
-    let mut b = Vec::new();
-    for _ in 0..n_iter {
-        x = key.mu * x * (1.0 - x);
-        binary_add(&mut b, x);
-    }
-    b
-}
-
-// extracts 8 bits following the decimal point
-// and appends them to the binary sequence
-#[inline(always)]
-fn binary_add(b: &mut Vec<u8>, x: f64) {
-    let mut x = x.fract();
-    for _ in 0..8 {
-        x *= 2.0;
-        b.push(x.floor() as u8);
-        x = x.fract();
-    }
-}
\ No newline at end of file
+    bitsequence(&mut LogisticMap::new(key), n_iter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chaotic::MapKind;
+
+    #[test]
+    fn fixed_test_vectors() {
+        // x_0, mu, m, x after 1, 2 and 1000 iterations, first 16 bytes
+        let vectors = [
+            (0.1, 3.9999, 0x7fff2e48e8a71c00, [0x5c285ec3dab5c383, 0xebebce9a5e17faec, 0xfffb0f36c75b0e9f], "5ceb4ad295f81c66f52887ff030d33a4"),
+            (0.5, 3.99, 0x7fae147ae147b000, [0xff5c28f5c28f6000, 0x028c165907d9031f, 0xc2d14dd4346b42f9], "ff020a2682ff030b2d94f91a5fef3dbb"),
+            (0.1234, 3.97, 0x7f0a3d70a3d70c00, [0x6df01497c00f4a9e, 0xf9054c0bdf6ee47b, 0x1a1cbfe7c5fc48a6], "6df91a5fed42c3b6cf9cf137abe06ef9"),
+        ];
+        for (x_0, mu, m, states, bytes) in vectors {
+            let key = SecretKey::new(x_0, mu).unwrap();
+            let mut map = FixedLogisticMap::new(&key);
+            assert_eq!(map.m, m);
+            assert_eq!([map.step(), map.step()], states[..2]);
+            assert_eq!((2..1000).map(|_| map.step()).last(), Some(states[2]));
+            let mut map = FixedLogisticMap::new(&key);
+            let hex = (0..16).map(|_| format!("{:02x}", map.next_bits(8))).collect::<String>();
+            assert_eq!(hex, bytes, "x_0 = {}, mu = {}", x_0, mu);
+        }
+    }
+
+    #[test]
+    fn rejects_weak_keys() {
+        let weak = [
+            // not chaotic: the orbit converges to a fixed point
+            (0.1234, 2.0),
+            // reaches 1, then stays at 0
+            (0.5, 4.0),
+            // outside (0, 1)
+            (0.0, 3.9),
+            (1.0, 3.9),
+            (-0.25, 3.9),
+            (1.5, 3.9),
+            (f64::NAN, 3.9),
+            (0.1234, f64::NAN),
+            // in the period-3 window
+            (0.1234, 3.83),
+        ];
+        for (x_0, mu) in weak {
+            assert!(matches!(SecretKey::new(x_0, mu), Err(Error::WeakKey(_))), "x_0 = {}, mu = {}", x_0, mu);
+            // keys built through their fields are checked when the map is built
+            assert!(MapKind::Logistic.build(&SecretKey { x_0, mu }).is_err(), "x_0 = {}, mu = {}", x_0, mu);
+        }
+        for (x_0, mu) in [(0.1234, 3.97), (0.1, 3.9999), (0.5, 3.99)] {
+            assert!(SecretKey::new(x_0, mu).is_ok(), "x_0 = {}, mu = {}", x_0, mu);
+        }
+    }
+
+    #[test]
+    fn big_map_rejects_keys_out_of_range() {
+        for (x_0, mu) in [(0.0, 3.9), (1.0, 3.9), (f64::NAN, 3.9), (0.5, 0.0), (0.5, 4.5), (0.5, f64::NAN)] {
+            let key = SecretKey { x_0, mu };
+            assert!(matches!(BigLogisticMap::new(&key, 64), Err(Error::WeakKey(_))), "x_0 = {}, mu = {}", x_0, mu);
+            assert!(MapKind::BigLogistic.build(&key).is_err(), "x_0 = {}, mu = {}", x_0, mu);
+        }
+        // a key below 2^-20 loses its last bits at 64 bits, not at 128
+        let key = SecretKey { x_0: 0.1234 * 2f64.powi(-20), mu: 3.9 };
+        assert_ne!(BigLogisticMap::new(&key, 64).unwrap().x.to_f64(), key.x_0);
+        assert_eq!(BigLogisticMap::new(&key, 128).unwrap().x.to_f64(), key.x_0);
+    }
+}
@@ -1,105 +1,414 @@
-use std::{fs::File, io::BufRead};
+use std::{process::ExitCode, str::FromStr};
 
-use get_permutation_matrix::{apply_permutation_matrix, get_permutation_matrix};
+use hcie_rs::{
+    apply_permutation_matrix, decrypt_color, encrypt_color, get_permutation_matrix,
+    img_array::{self, img_to_pixels, pixels_to_img},
+    is_permutation,
+    kdf::{derive_key_file, KdfParams, DEFAULT_ITERATIONS},
+    BitExtraction, Ciphertext, ColorMode, HcieParams, Header, KeyFile, SecretKey, Substitute,
+};
+use image::{ColorType, DynamicImage, ImageBuffer, Pixel};
 use ndarray::Array2;
 
-mod rotate;
-mod sub_hcie;
-mod encrypt;
-mod logistic;
-mod img_array;
-mod get_permutation_matrix;
-mod mean;
-
-fn main() {
-    let secret_key = logistic::SecretKey::new(0.1, 3.9999);
-    
-    // create array with file names
-    let filenames = ["baboon", "female", "earth", "house", "peppers", "splash"];
-    let s_m = 32;
-    let s_n = 32;
-    let mut original_imgs = vec![];
-    let mut encrypted_imgs = vec![];
-    for filename in filenames.iter() {
-        // open image
-        let img = img_array::open_grayscale(&format!("imgs_256/{}.png", filename));
-        // convert to array
-        let array = img_array::img_to_array(&img);
-        original_imgs.push(array.clone());
-        // encrypt
-        let encrypted = encrypt::encrypt(&array, s_m, s_n, &secret_key);
-        encrypted_imgs.push(encrypted.clone());
-        assert!(is_permutation(&array, &encrypted));
-        // convert to image
-        let img = img_array::array_to_img(&encrypted);
-        // save image
-        img.save(&format!("imgs_256_encrypted/{}.png", filename)).unwrap();
-    }
-    println!("done encrypting");
-
-    for n in 1..=3 {
-        println!("n = {}", n);
-        let (w, w_inv) = get_permutation_matrix(&original_imgs[0..n], &encrypted_imgs[0..n]);
-        // let w_inv = read_matrix_from_txt(256, 256, "w_inv.txt");
-
-        // print_matrix_to_txt(&w, "w.txt");
-        // print_matrix_to_txt(&w_inv, "w_inv.txt");
-        // let decrypted_first_img = apply_permutation_matrix(&w_inv, &encrypted_imgs[0]);
-        // // save image
-        // let img = img_array::array_to_img(&decrypted_first_img);
-        // img.save("imgs_256_decrypted/baboon.png").unwrap();
-
-        // decrypt all imgs
-        for (i, filename) in filenames.iter().enumerate() {
-            let decrypted = apply_permutation_matrix(&w_inv, &encrypted_imgs[i]);
-            // save image
-            let img = img_array::array_to_img(&decrypted);
-            img.save(&format!("imgs_256_decrypted/{}_{}.png", filename, n)).unwrap();
+const USAGE: &str = "\
+Usage: hcie_rs <COMMAND> [OPTIONS]
+
+Commands:
+  encrypt   Encrypt an 8 or 16-bit image
+  decrypt   Decrypt an image produced by `encrypt`
+  keygen    Create a new random key file
+  attack    Recover the permutation from known plain/encrypted pairs
+            and use it to decrypt another image
+
+Options for encrypt and decrypt:
+  -i, --input <PATH>    Input image
+  -o, --output <PATH>   Output image (use a lossless format, e.g. png)
+      --header <PATH>   Header of the encrypted image, which records its
+                        original size, nonce and digest [default: <encrypted image>.json]
+  -k, --key <PATH>      Key file, holding the key and the parameters below
+      --x0 <X0>         Initial condition of the logistic map, in (0, 1)
+      --mu <MU>         Parameter of the logistic map (with --x0, instead of --key)
+      --passphrase <P>  Derive the key and alpha, beta, gamma from a passphrase
+      --salt <SALT>     Salt for --passphrase [default: random when encrypting,
+                        read from the header when decrypting]
+      --kdf-iter <N>    PBKDF2 iterations for --passphrase [default: 100000]
+      --color <MODE>    gray, channels (each channel permuted with its own
+                        keystream) or joint (pixels keep their colour) [default: gray]
+      --sm <S_M>        Block height [default: 32]
+      --sn <S_N>        Block width [default: 32]
+      --blocks <LIST>   Block sizes of a deeper hierarchy, from the largest to
+                        the smallest, each dividing the previous one, e.g.
+                        256x256,64x64,16x16 or 256,64,16 (instead of --sm, --sn)
+      --permute <P>     both (the order of the blocks and their pixels), blocks
+                        or pixels [default: both]
+      --iter <N>        Rounds of rotations per block [default: 2]
+      --alpha <ALPHA>   Rotations are by alpha + beta*b + gamma*b' positions [default: 4]
+      --beta <BETA>     [default: 2]
+      --gamma <GAMMA>   [default: 1]
+      --map <MAP>       Chaotic map: logistic, fixed-logistic, big-logistic,
+                        tent, henon, chebyshev, piecewise-linear or skew-tent
+                        [default: logistic]
+      --extract <M>     Bits taken from every iterate: fraction (the first
+                        bits after the binary point), threshold (x > 0.5) or
+                        mantissa (the last bits of the f64) [default: fraction]
+      --bits <N>        Bits per iterate, for fraction and mantissa [default: 8]
+      --skip <N>        Iterations skipped before taking bits [default: 0]
+      --diffusion <D>   Also change the pixel values, chaining them with the
+                        keystream: none, xor or additive [default: none]
+      --level-keys <K>  shared (one keystream for every level of the hierarchy)
+                        or derived (a keystream per level, from subkeys of
+                        the key) [default: shared]
+      --plaintext-dependent <true|false>
+                        Seed the keystream with a keyed digest of the image,
+                        checked when decrypting [default: false]
+                        (--sm to --plaintext-dependent override the values in
+                        the key file)
+
+Options for keygen:
+  -o, --output <PATH>   Key file to create [default: print to stdout]
+      --passphrase, --salt, --kdf-iter as above
+      --sm to --plaintext-dependent as above
+
+Options for attack:
+      --plain <PATH>    Known plain image (repeat for every pair)
+      --cipher <PATH>   Encrypted version of the matching --plain image
+  -i, --input <PATH>    Encrypted image to decrypt
+  -o, --output <PATH>   Output image
+
+Exit status is 0 on success, 1 on failure and 2 on invalid usage.";
+
+enum CliError {
+    Usage(String),
+    Failure(String),
+}
+
+type CliResult<T> = Result<T, CliError>;
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(msg)) => {
+            eprintln!("error: {}\n\nRun `hcie_rs --help` for usage.", msg);
+            ExitCode::from(2)
         }
+        Err(CliError::Failure(msg)) => {
+            eprintln!("error: {}", msg);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+const PARAM_OPTIONS: &[&str] = &["sm", "sn", "blocks", "permute", "iter", "alpha", "beta", "gamma", "map", "extract", "bits", "skip", "diffusion", "level-keys", "plaintext-dependent"];
+const KEY_OPTIONS: &[&str] = &["key", "passphrase", "salt", "kdf-iter", "x0", "mu"];
+const CIPHER_OPTIONS: &[&str] = &["input", "output", "header", "color"];
+
+fn run(args: &[String]) -> CliResult<()> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(CliError::Usage("missing command".to_string()));
+    };
+    match command.as_str() {
+        "encrypt" => cmd_encrypt(&Options::parse(rest, &[CIPHER_OPTIONS, KEY_OPTIONS, PARAM_OPTIONS].concat())?),
+        "decrypt" => cmd_decrypt(&Options::parse(rest, &[CIPHER_OPTIONS, KEY_OPTIONS, PARAM_OPTIONS].concat())?),
+        "keygen" => cmd_keygen(&Options::parse(rest, &[&["output", "passphrase", "salt", "kdf-iter"], PARAM_OPTIONS].concat())?),
+        "attack" => cmd_attack(&Options::parse(rest, &["plain", "cipher", "input", "output"])?),
+        "-h" | "--help" | "help" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(CliError::Usage(format!("unknown command `{}`", other))),
     }
 }
 
-//when sorted, x and its permutation y must be equal
-pub fn is_permutation(x: &Array2<u8>, y: &Array2<u8>) -> bool {
-    // convert to Vec<u8>
-    let mut x = x.iter().cloned().collect::<Vec<_>>();
-    let mut y = y.iter().cloned().collect::<Vec<_>>();
-    // sort
-    x.sort();
-    y.sort();
-    // compare
-    x == y
+fn cmd_encrypt(opts: &Options) -> CliResult<()> {
+    cipher(opts, true)
+}
+
+fn cmd_decrypt(opts: &Options) -> CliResult<()> {
+    cipher(opts, false)
 }
 
-pub fn print_matrix_to_txt(m: &Array2<(usize, usize)>, filename: &str) {
-    use std::io::Write;
-    let mut file = File::create(filename).unwrap();
-    //do not write as binary
-    for i in 0..m.shape()[0] {
-        for j in 0..m.shape()[1] {
-            let (a, b) = m[[i,j]];
-            write!(file, "{} {} ", a, b).unwrap();
+/// Encrypts or decrypts the input image, keeping its bit depth.
+///
+/// The header of the encrypted image, which records its original size and
+/// nonce, is written next to it and read back to decrypt it.
+fn cipher(opts: &Options, encrypting: bool) -> CliResult<()> {
+    let input = opts.required("input")?;
+    let output = opts.required("output")?;
+    let header_path = opts.get("header").map(str::to_string).unwrap_or_else(|| format!("{}.json", if encrypting { output } else { input }));
+    let header = if encrypting { None } else { Some(read_header(&header_path)?) };
+    let (KeyFile { key, params, .. }, kdf) = opts.key_file(encrypting, header.as_ref().and_then(|h| h.kdf.as_ref()))?;
+    // a grayscale image is a colour image with a single channel
+    let mode = opts.color()?.unwrap_or(ColorMode::Joint);
+    let img = open(input)?;
+    let sixteen_bits = matches!(img.color(), ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16);
+    let result = match (opts.color()?.is_some(), sixteen_bits, img.color().has_alpha()) {
+        (false, false, _) => cipher_image(&img.to_luma8(), mode, &params, &key, encrypting, header),
+        (false, true, _) => cipher_image(&img.to_luma16(), mode, &params, &key, encrypting, header),
+        (true, false, false) => cipher_image(&img.to_rgb8(), mode, &params, &key, encrypting, header),
+        (true, false, true) => cipher_image(&img.to_rgba8(), mode, &params, &key, encrypting, header),
+        (true, true, false) => cipher_image(&img.to_rgb16(), mode, &params, &key, encrypting, header),
+        (true, true, true) => cipher_image(&img.to_rgba16(), mode, &params, &key, encrypting, header),
+    };
+    let (result, header) = result.map_err(failure)?;
+    save_image(&result, output)?;
+    if let Some(mut header) = header {
+        // the salt is needed to derive the key again when decrypting
+        header.kdf = kdf;
+        std::fs::write(&header_path, header.to_json())
+            .map_err(|e| CliError::Failure(format!("cannot save {}: {}", header_path, e)))?;
+    }
+    Ok(())
+}
+
+/// Encrypts `img`, returning the encrypted image and its header,
+/// or decrypts it using `header`.
+fn cipher_image<P>(img: &ImageBuffer<P, Vec<P::Subpixel>>, mode: ColorMode, params: &HcieParams, key: &SecretKey, encrypting: bool, header: Option<Header>) -> Result<(DynamicImage, Option<Header>), hcie_rs::Error>
+where
+    P: Pixel + Substitute,
+    P::Subpixel: Substitute,
+    DynamicImage: From<ImageBuffer<P, Vec<P::Subpixel>>>,
+{
+    if encrypting {
+        let c = encrypt_color(img, mode, params, key)?;
+        Ok((DynamicImage::from(pixels_to_img(&c.data)), Some(c.header)))
+    } else {
+        let c = Ciphertext { data: img_to_pixels(img), header: header.expect("the header is read before decrypting") };
+        Ok((DynamicImage::from(decrypt_color(&c, mode, params, key)?), None))
+    }
+}
+
+/// Reads the header of an encrypted image, which holds the nonce needed
+/// to decrypt it.
+fn read_header(path: &str) -> CliResult<Header> {
+    let json = std::fs::read_to_string(path).map_err(|e| CliError::Failure(format!("cannot open header {}: {}", path, e)))?;
+    Header::from_json(&json).map_err(failure)
+}
+
+fn cmd_keygen(opts: &Options) -> CliResult<()> {
+    let key_file = if opts.get("passphrase").is_some() {
+        // a new key gets a new salt, as when encrypting
+        opts.key_file(true, None)?.0
+    } else {
+        let unit = |r: u64| (r >> 11) as f64 / (1u64 << 53) as f64;
+        // draw until the key is not weak
+        let key = loop {
+            if let Ok(key) = SecretKey::new(0.01 + 0.98 * unit(random_u64()?), 3.9 + 0.0999 * unit(random_u64()?)) {
+                break key;
+            }
+        };
+        KeyFile::new(key, opts.params(HcieParams::default())?)
+    };
+    match opts.get("output") {
+        Some(path) => key_file.save(path).map_err(|e| CliError::Failure(format!("cannot save {}: {}", path, e))),
+        None => {
+            println!("{}", key_file.to_json());
+            Ok(())
         }
-        writeln!(file).unwrap();
     }
 }
 
-pub fn read_matrix_from_txt(m: usize, n: usize, filename: &str) -> Array2<(usize, usize)> {
-    let file = File::open(filename).expect("Error opening file");
-    let reader = std::io::BufReader::new(file);
-    let mut w = Array2::<(usize, usize)>::from_elem((m, n), (0, 0));
+fn cmd_attack(opts: &Options) -> CliResult<()> {
+    let plain = opts.all("plain");
+    let cipher = opts.all("cipher");
+    if plain.is_empty() || plain.len() != cipher.len() {
+        return Err(CliError::Usage("expected the same, non-zero, number of --plain and --cipher images".to_string()));
+    }
+    let xs = plain.iter().map(|path| load(path)).collect::<CliResult<Vec<_>>>()?;
+    let ys = cipher.iter().map(|path| load(path)).collect::<CliResult<Vec<_>>>()?;
+    for ((x, y), path) in xs.iter().zip(ys.iter()).zip(cipher.iter()) {
+        if x.dim() != xs[0].dim() || y.dim() != x.dim() {
+            return Err(CliError::Failure(format!("{} does not have the same size as the other images", path)));
+        }
+        if !is_permutation(x, y) {
+            return Err(CliError::Failure(format!("{} is not a permutation of its plain image", path)));
+        }
+    }
+    let target = load(opts.required("input")?)?;
+    if target.dim() != xs[0].dim() {
+        return Err(CliError::Failure("the image to decrypt must have the same size as the known pairs".to_string()));
+    }
+    let Some((_w, w_inv)) = get_permutation_matrix(&xs, &ys) else {
+        return Err(CliError::Failure("no single permutation maps every plain image to its encrypted image; were they encrypted with the same key and nonce?".to_string()));
+    };
+    save(&apply_permutation_matrix(&w_inv, &target), opts.required("output")?)
+}
 
-    for (i, line) in reader.lines().enumerate() {
-        let line = line.expect("Error reading line from file");
-        let mut numbers = line.split_whitespace().map(|s| s.parse::<usize>());
-        
-        for j in 0..n {
-            let x = numbers.next().expect("Error parsing number");
-            let y = numbers.next().expect("Error parsing number");
+// drawn from the random number generator of the operating system, for
+// keys and salts
+fn random_u64() -> CliResult<u64> {
+    let mut bytes = [0; 8];
+    getrandom::getrandom(&mut bytes).map_err(|e| CliError::Failure(format!("cannot draw random bytes: {}", e)))?;
+    Ok(u64::from_be_bytes(bytes))
+}
 
-            w[(i, j)] = (x.unwrap(), y.unwrap());
+fn failure(e: hcie_rs::Error) -> CliError {
+    CliError::Failure(e.to_string())
+}
+
+fn open(path: &str) -> CliResult<DynamicImage> {
+    image::open(path).map_err(|e| CliError::Failure(format!("cannot open {}: {}", path, e)))
+}
+
+fn load(path: &str) -> CliResult<Array2<u8>> {
+    Ok(img_array::img_to_array(&open(path)?.to_luma8()))
+}
+
+fn save_image(img: &DynamicImage, path: &str) -> CliResult<()> {
+    img.save(path)
+        .map_err(|e| CliError::Failure(format!("cannot save {}: {}", path, e)))
+}
+
+fn save(f: &Array2<u8>, path: &str) -> CliResult<()> {
+    save_image(&DynamicImage::from(img_array::array_to_img(f)), path)
+}
+
+/// Command line options of the form `--name value`, in the order they were given.
+struct Options {
+    values: Vec<(String, String)>,
+}
+
+impl Options {
+    fn parse(args: &[String], allowed: &[&str]) -> CliResult<Self> {
+        let mut values = vec![];
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let name = match arg.as_str() {
+                "-i" => "input",
+                "-o" => "output",
+                "-k" => "key",
+                _ => arg
+                    .strip_prefix("--")
+                    .ok_or_else(|| CliError::Usage(format!("unexpected argument `{}`", arg)))?,
+            };
+            if !allowed.contains(&name) {
+                return Err(CliError::Usage(format!("unknown option `{}`", arg)));
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| CliError::Usage(format!("missing value for `{}`", arg)))?;
+            values.push((name.to_string(), value.clone()));
+        }
+        Ok(Self { values })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.values.iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+
+    fn all(&self, name: &str) -> Vec<&str> {
+        self.values.iter().filter(|(n, _)| n == name).map(|(_, v)| v.as_str()).collect()
+    }
+
+    fn required(&self, name: &str) -> CliResult<&str> {
+        self.get(name)
+            .ok_or_else(|| CliError::Usage(format!("missing required option `--{}`", name)))
+    }
+
+    fn parsed<T: FromStr>(&self, name: &str) -> CliResult<Option<T>> {
+        self.get(name)
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| CliError::Usage(format!("invalid value `{}` for `--{}`", v, name)))
+            })
+            .transpose()
+    }
+
+    /// The key and parameters, from `--key`, `--passphrase` or `--x0` and `--mu`,
+    /// with the parameters overridden by the command line.
+    ///
+    /// A key derived from a passphrase uses the salt given by `--salt`, or by
+    /// `kdf` (read from a header), or, when `encrypting`, a new random salt;
+    /// the salt used is returned.
+    fn key_file(&self, encrypting: bool, kdf: Option<&KdfParams>) -> CliResult<(KeyFile, Option<KdfParams>)> {
+        let sources = [self.get("key").is_some(), self.get("passphrase").is_some(), self.get("x0").is_some() || self.get("mu").is_some()];
+        if sources.iter().filter(|&&s| s).count() > 1 {
+            return Err(CliError::Usage("use only one of `--key`, `--passphrase` or `--x0` and `--mu`".to_string()));
+        }
+        if let Some(path) = self.get("key") {
+            let key_file = KeyFile::load(path).map_err(|e| CliError::Failure(format!("cannot load {}: {}", path, e)))?;
+            return Ok((KeyFile::new(key_file.key, self.params(key_file.params)?), None));
+        }
+        if let Some(passphrase) = self.get("passphrase") {
+            let salt = match (self.get("salt"), kdf) {
+                (Some(salt), _) => salt.to_string(),
+                (None, Some(kdf)) => kdf.salt.clone(),
+                (None, None) if encrypting => format!("{:016x}{:016x}", random_u64()?, random_u64()?),
+                // a random salt would silently derive a wrong key
+                (None, None) => return Err(CliError::Usage("missing `--salt`: the header does not record the salt of the passphrase".to_string())),
+            };
+            let iterations = match (self.parsed("kdf-iter")?, kdf) {
+                (Some(iterations), _) => iterations,
+                (None, Some(kdf)) => kdf.iterations,
+                (None, None) => DEFAULT_ITERATIONS,
+            };
+            if iterations == 0 {
+                return Err(CliError::Usage("`--kdf-iter` must be positive".to_string()));
+            }
+            let derived = derive_key_file(passphrase, salt.as_bytes(), iterations, &self.params(HcieParams::default())?);
+            // explicit rotation parameters still take precedence over the derived ones
+            let params = self.params(derived.params)?;
+            return Ok((KeyFile::new(derived.key, params), Some(KdfParams { salt, iterations })));
         }
+        let x_0 = self.parsed("x0")?.ok_or_else(|| CliError::Usage("missing required option `--key`, `--passphrase` or `--x0`".to_string()))?;
+        let mu = self.parsed("mu")?.ok_or_else(|| CliError::Usage("missing required option `--mu`".to_string()))?;
+        let key = SecretKey::new(x_0, mu).map_err(|e| CliError::Usage(e.to_string()))?;
+        Ok((KeyFile::new(key, self.params(HcieParams::default())?), None))
     }
 
-    w
-}
\ No newline at end of file
+    fn color(&self) -> CliResult<Option<ColorMode>> {
+        match self.get("color") {
+            None | Some("gray") => Ok(None),
+            Some("channels") => Ok(Some(ColorMode::PerChannel)),
+            Some("joint") => Ok(Some(ColorMode::Joint)),
+            Some(other) => Err(CliError::Usage(format!("invalid value `{}` for `--color`", other))),
+        }
+    }
+
+    /// The sizes of the blocks of every level, from `--blocks`, e.g.
+    /// `256x256,64x64,16x16` or `256,64,16` for square blocks.
+    fn blocks(&self) -> CliResult<Option<Vec<(usize, usize)>>> {
+        let Some(list) = self.get("blocks") else {
+            return Ok(None);
+        };
+        if self.get("sm").is_some() || self.get("sn").is_some() {
+            return Err(CliError::Usage("use either `--blocks` or `--sm` and `--sn`".to_string()));
+        }
+        let invalid = || CliError::Usage(format!("invalid value `{}` for `--blocks`", list));
+        list.split(',')
+            .map(|size| {
+                let (s_m, s_n) = size.split_once('x').unwrap_or((size, size));
+                Ok((s_m.parse().map_err(|_| invalid())?, s_n.parse().map_err(|_| invalid())?))
+            })
+            .collect::<CliResult<Vec<_>>>()
+            .map(Some)
+    }
+
+    fn params(&self, default: HcieParams) -> CliResult<HcieParams> {
+        let default = match self.blocks()? {
+            Some(blocks) => HcieParams { s_m: blocks[0].0, s_n: blocks[0].1, sub_blocks: blocks[1..].to_vec(), ..default },
+            None => default,
+        };
+        let params = HcieParams {
+            s_m: self.parsed("sm")?.unwrap_or(default.s_m),
+            s_n: self.parsed("sn")?.unwrap_or(default.s_n),
+            sub_blocks: default.sub_blocks,
+            permutation: self.parsed("permute")?.unwrap_or(default.permutation),
+            n_iter: self.parsed("iter")?.unwrap_or(default.n_iter),
+            alpha: self.parsed("alpha")?.unwrap_or(default.alpha),
+            beta: self.parsed("beta")?.unwrap_or(default.beta),
+            gamma: self.parsed("gamma")?.unwrap_or(default.gamma),
+            map: self.parsed("map")?.unwrap_or(default.map),
+            extraction: BitExtraction {
+                method: self.parsed("extract")?.unwrap_or(default.extraction.method),
+                bits: self.parsed("bits")?.unwrap_or(default.extraction.bits),
+                skip: self.parsed("skip")?.unwrap_or(default.extraction.skip),
+            },
+            diffusion: self.parsed("diffusion")?.unwrap_or(default.diffusion),
+            level_keys: self.parsed("level-keys")?.unwrap_or(default.level_keys),
+            plaintext_dependent: self.parsed("plaintext-dependent")?.unwrap_or(default.plaintext_dependent),
+        };
+        params.validate().map_err(|e| CliError::Usage(e.to_string()))?;
+        Ok(params)
+    }
+}
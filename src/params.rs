@@ -0,0 +1,277 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{chaotic::{BitExtraction, MapKind}, diffusion::Diffusion, error::Error};
+
+/// Parameters of the HCIE scheme, shared by [`encrypt`](crate::encrypt())
+/// and [`decrypt`](crate::decrypt()).
+///
+/// Every permutation of an `s_m x s_n` matrix runs `n_iter` rounds of
+/// rotations; in each round all rows, columns and diagonals are rotated by
+/// `p = alpha + beta * b_q + gamma * b_{q+1}` positions, where `b_q` and
+/// `b_{q+1}` are bits of the chaotic sequence.
+///
+/// The image is split into `s_m x s_n` blocks, and each block can in turn
+/// be split into smaller blocks, as listed in `sub_blocks`: at every level,
+/// the order of the blocks inside the enclosing one is permuted, and the
+/// pixels are only permuted inside the blocks of the last level.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HcieParams {
+    /// Block height.
+    pub s_m: usize,
+    /// Block width.
+    pub s_n: usize,
+    /// Sizes of the blocks of the deeper levels of the hierarchy, from the
+    /// largest to the smallest. Each one must divide the previous one (the
+    /// first one, `s_m x s_n`). Empty by default, for the two levels of the
+    /// original scheme.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sub_blocks: Vec<(usize, usize)>,
+    /// Which levels of the hierarchy are permuted.
+    #[serde(default)]
+    pub permutation: PermutationMode,
+    /// Number of rounds of rotations applied to every block.
+    pub n_iter: usize,
+    pub alpha: usize,
+    pub beta: usize,
+    pub gamma: usize,
+    /// Chaotic map generating the bit sequence.
+    #[serde(default)]
+    pub map: MapKind,
+    /// How the bits are taken from the orbit of the map.
+    #[serde(default)]
+    pub extraction: BitExtraction,
+    /// Substitution of the pixel values after the permutation.
+    #[serde(default)]
+    pub diffusion: Diffusion,
+    /// Whether all the levels of the hierarchy share one keystream.
+    #[serde(default)]
+    pub level_keys: LevelKeys,
+    /// Makes the keystream depend on the plain image.
+    ///
+    /// Otherwise, the permutation only depends on the key and the nonce:
+    /// whoever can have images of their choice encrypted with a given nonce
+    /// learns the permutation, and can decrypt any image encrypted with it.
+    /// When set, a keyed digest of the image is mixed into the initial state
+    /// of the map, so every image is permuted differently even with the same
+    /// nonce. The digest is recorded in the header, and checked when
+    /// decrypting. In exchange, the image must be read twice, identical
+    /// images encrypted with the same nonce give identical digests (which
+    /// reveals that they are equal), and a single changed pixel changes the
+    /// whole permutation.
+    #[serde(default)]
+    pub plaintext_dependent: bool,
+}
+
+impl Default for HcieParams {
+    /// 32x32 blocks, two rounds, `(alpha, beta, gamma) = (4, 2, 1)`
+    /// 8 bits from every iterate of the logistic map, and no diffusion.
+    fn default() -> Self {
+        Self {
+            s_m: 32,
+            s_n: 32,
+            sub_blocks: Vec::new(),
+            permutation: PermutationMode::Both,
+            n_iter: 2,
+            alpha: 4,
+            beta: 2,
+            gamma: 1,
+            map: MapKind::Logistic,
+            extraction: BitExtraction::default(),
+            diffusion: Diffusion::None,
+            level_keys: LevelKeys::Shared,
+            plaintext_dependent: false,
+        }
+    }
+}
+
+impl HcieParams {
+    /// Default parameters with blocks of size `s_m x s_n`.
+    pub fn new(s_m: usize, s_n: usize) -> Self {
+        Self { s_m, s_n, ..Self::default() }
+    }
+
+    /// Default parameters with a hierarchy of blocks of the given sizes,
+    /// from the largest to the smallest, e.g. `[(256, 256), (64, 64), (16, 16), (4, 4)]`.
+    ///
+    /// Panics if `blocks` is empty.
+    pub fn with_blocks(blocks: &[(usize, usize)]) -> Self {
+        let (s_m, s_n) = blocks[0];
+        Self { sub_blocks: blocks[1..].to_vec(), ..Self::new(s_m, s_n) }
+    }
+
+    /// Number of levels of the hierarchy, each with its own keystream when
+    /// [`level_keys`](Self::level_keys) is [`LevelKeys::Derived`]: one per
+    /// block order table (the first one for the `s_m x s_n` blocks, then one
+    /// per entry of `sub_blocks`), and the pixels of the smallest blocks.
+    pub fn levels(&self) -> usize {
+        self.sub_blocks.len() + 2
+    }
+
+    /// Sizes of the blocks of every level, from the largest to the smallest.
+    pub fn blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks = vec![(self.s_m, self.s_n)];
+        blocks.extend(&self.sub_blocks);
+        blocks
+    }
+
+    /// Checks that the parameters describe a valid (non-trivial) permutation.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.s_m == 0 || self.s_n == 0 {
+            return Err(Error::InvalidParams(format!("block size {}x{} must be positive", self.s_m, self.s_n)));
+        }
+        for (&(m, n), &(s_m, s_n)) in self.blocks().iter().zip(&self.sub_blocks) {
+            if s_m == 0 || s_n == 0 || !m.is_multiple_of(s_m) || !n.is_multiple_of(s_n) {
+                return Err(Error::InvalidParams(format!("block size {}x{} does not divide the enclosing block size {}x{}", s_m, s_n, m, n)));
+            }
+        }
+        if self.n_iter == 0 {
+            return Err(Error::InvalidParams("n_iter must be positive".to_string()));
+        }
+        if self.alpha == 0 {
+            // with alpha = 0 some rounds would not rotate anything
+            return Err(Error::InvalidParams("alpha must be positive".to_string()));
+        }
+        self.extraction.validate()
+    }
+
+    /// Checks that an `m x n` image can be encrypted with these parameters.
+    pub fn validate_for(&self, m: usize, n: usize) -> Result<(), Error> {
+        self.validate()?;
+        if m == 0 || n == 0 {
+            return Err(Error::InvalidDimensions("the image is empty".to_string()));
+        }
+        // the blocks are numbered with u32 in the block order table
+        let (m_pad, n_pad) = self.padded_dim(m, n);
+        if (m_pad / self.s_m).saturating_mul(n_pad / self.s_n) > u32::MAX as usize {
+            return Err(Error::InvalidDimensions(format!("too many {}x{} blocks for an image of size {}x{}", self.s_m, self.s_n, m, n)));
+        }
+        Ok(())
+    }
+
+    /// Dimensions of an `m x n` image once padded to a multiple of the block size.
+    pub fn padded_dim(&self, m: usize, n: usize) -> (usize, usize) {
+        (m.next_multiple_of(self.s_m), n.next_multiple_of(self.s_n))
+    }
+
+    /// Number of bits consumed by one permutation of an `s_m x s_n` matrix.
+    pub fn bits_per_block(&self) -> usize {
+        self.n_iter * (3*self.s_m + 3*self.s_n - 2)
+    }
+}
+
+/// Which permutations of the hierarchy are applied.
+///
+/// Restricting the permutation is meant for ablation studies, to measure
+/// what each level contributes, and for images that only need to be
+/// obscured cheaply (e.g. thumbnails): permuting the blocks only moves
+/// whole blocks, whose contents stay legible, while permuting the pixels
+/// only keeps every pixel in its block. The keystream of a permutation that
+/// is not applied is not generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PermutationMode {
+    /// The order of the blocks and the pixels of every block, as in the
+    /// original scheme.
+    #[default]
+    Both,
+    /// Only the order of the blocks, at every level of the hierarchy.
+    Blocks,
+    /// Only the pixels inside the smallest blocks.
+    Pixels,
+}
+
+impl PermutationMode {
+    /// Whether the order of the blocks is permuted.
+    pub fn blocks(self) -> bool {
+        self != PermutationMode::Pixels
+    }
+
+    /// Whether the pixels inside the blocks are permuted.
+    pub fn pixels(self) -> bool {
+        self != PermutationMode::Blocks
+    }
+}
+
+impl FromStr for PermutationMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "both" => Ok(PermutationMode::Both),
+            "blocks" => Ok(PermutationMode::Blocks),
+            "pixels" => Ok(PermutationMode::Pixels),
+            _ => Err(Error::InvalidParams(format!("unknown permutation `{}`", s))),
+        }
+    }
+}
+
+/// Keys of the levels of the hierarchy.
+///
+/// With separate keys, each level of the hierarchy (see [`HcieParams::levels`])
+/// draws from its own keystream, so that the contribution of every level to
+/// the security of the scheme can be studied on its own, and the key of one
+/// level can be changed without changing the others (see
+/// [`encrypt_with_keys`](crate::encrypt_with_keys)).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LevelKeys {
+    /// One keystream for every level, as in the original scheme.
+    #[default]
+    Shared,
+    /// One keystream per level, from subkeys derived from the secret key
+    /// with [`level_key`](crate::kdf::level_key).
+    Derived,
+}
+
+impl FromStr for LevelKeys {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "shared" => Ok(LevelKeys::Shared),
+            "derived" => Ok(LevelKeys::Derived),
+            _ => Err(Error::InvalidParams(format!("unknown level keys `{}`", s))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chaotic::ExtractionMethod;
+
+    #[test]
+    fn rejects_invalid_params() {
+        let extraction = |method, bits| BitExtraction { method, bits, ..BitExtraction::default() };
+        let invalid = [
+            HcieParams::new(0, 8),
+            HcieParams::new(8, 0),
+            HcieParams { n_iter: 0, ..HcieParams::new(8, 8) },
+            HcieParams { alpha: 0, ..HcieParams::new(8, 8) },
+            HcieParams { extraction: extraction(ExtractionMethod::Fraction, 0), ..HcieParams::new(8, 8) },
+            HcieParams { extraction: extraction(ExtractionMethod::Fraction, 33), ..HcieParams::new(8, 8) },
+            HcieParams { extraction: extraction(ExtractionMethod::Mantissa, 53), ..HcieParams::new(8, 8) },
+        ];
+        for params in invalid {
+            assert!(matches!(params.validate(), Err(Error::InvalidParams(_))), "{:?}", params);
+        }
+        let params = HcieParams { extraction: extraction(ExtractionMethod::Mantissa, 52), ..HcieParams::new(8, 8) };
+        assert!(params.validate().is_ok());
+        assert!(matches!(params.validate_for(0, 16), Err(Error::InvalidDimensions(_))));
+        // the same parameters are read back by the decrypting side
+        assert_eq!(serde_json::from_str::<HcieParams>(&serde_json::to_string(&params).unwrap()).unwrap(), params);
+    }
+
+    #[test]
+    fn sub_blocks_divide_their_parent() {
+        for blocks in [&[(16, 16), (6, 8)][..], &[(16, 16), (8, 8), (3, 4)], &[(16, 16), (8, 0)], &[(16, 16), (32, 32)]] {
+            assert!(matches!(HcieParams::with_blocks(blocks).validate(), Err(Error::InvalidParams(_))), "{:?}", blocks);
+        }
+        let params = HcieParams::with_blocks(&[(16, 16), (8, 4), (4, 2), (1, 1)]);
+        assert!(params.validate().is_ok());
+        assert_eq!((params.levels(), params.blocks()), (5, vec![(16, 16), (8, 4), (4, 2), (1, 1)]));
+    }
+}
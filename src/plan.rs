@@ -0,0 +1,139 @@
+//! Permutations compiled once and applied to many images.
+
+use ndarray::Array2;
+
+use crate::{
+    ciphertext::{Ciphertext, Header, Nonce},
+    diffusion::Diffusion,
+    encrypt::{decrypt_with_maps, encrypt_with_maps, level_keys, seeded_maps},
+    error::Error,
+    logistic::SecretKey,
+    params::HcieParams,
+};
+
+/// The permutation applied by [`encrypt`](crate::encrypt()) to every image
+/// of a given size, for a given key, nonce and parameters, as a flat table
+/// of indices.
+///
+/// Building the plan runs the rotations once, on images holding the indices
+/// of their pixels; [`encrypt`](Self::encrypt) and [`decrypt`](Self::decrypt)
+/// then move every pixel in a single pass, giving the same results as
+/// [`encrypt`](crate::encrypt()) and [`decrypt`](crate::decrypt()).
+///
+/// Since every image is permuted identically, the images encrypted with a
+/// plan share a nonce, with the weakness that implies (see
+/// [`encrypt_with_nonce`](crate::encrypt_with_nonce)). A plan is a pure
+/// permutation, so it cannot be built for parameters with diffusion or a
+/// plaintext-dependent keystream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PermutationPlan {
+    /// Header of the images encrypted with the plan.
+    pub header: Header,
+    // dimensions of the encrypted images
+    dim: (usize, usize),
+    // index, in row-major order, of the plain pixel of every encrypted pixel
+    forward: Vec<usize>,
+    // index of the encrypted pixel of every plain pixel
+    inverse: Vec<usize>,
+}
+
+impl PermutationPlan {
+    /// Compiles the permutation of `m x n` images encrypted with `key` and `nonce`.
+    pub fn new(m: usize, n: usize, params: &HcieParams, key: &SecretKey, nonce: &Nonce) -> Result<Self, Error> {
+        Self::for_header(&Header::seeded(m, n, *nonce, None), params, key)
+    }
+
+    /// Compiles the permutation of the images with `header`, e.g. to decrypt
+    /// images encrypted by [`encrypt_with_nonce`](crate::encrypt_with_nonce).
+    pub fn for_header(header: &Header, params: &HcieParams, key: &SecretKey) -> Result<Self, Error> {
+        if params.diffusion != Diffusion::None || params.plaintext_dependent || header.digest.is_some() {
+            return Err(Error::InvalidParams("a permutation plan cannot diffuse the pixels nor depend on the plain image".to_string()));
+        }
+        let (m, n) = (header.m, header.n);
+        let keys = level_keys(params, key);
+        let maps = || seeded_maps(params, &keys, header.nonce.as_ref(), None);
+        let plain = Array2::from_shape_fn((m, n), |(i, j)| (i * n + j) as u64);
+        let forward = encrypt_with_maps(&plain, params, &mut maps()?)?.data;
+        let dim = forward.dim();
+        let cipher = Ciphertext { data: Array2::from_shape_fn(dim, |(i, j)| (i * dim.1 + j) as u64), header: header.clone() };
+        let inverse = decrypt_with_maps(&cipher, params, &mut maps()?)?;
+        Ok(Self {
+            header: header.clone(),
+            dim,
+            forward: forward.iter().map(|&k| k as usize).collect(),
+            inverse: inverse.iter().map(|&k| k as usize).collect(),
+        })
+    }
+
+    /// Encrypts `f`, which must have the size of the plan.
+    pub fn encrypt<T>(&self, f: &Array2<T>) -> Result<Ciphertext<T>, Error>
+    where
+        T: Copy,
+    {
+        let (m, n) = (self.header.m, self.header.n);
+        if f.dim() != (m, n) {
+            return Err(Error::InvalidDimensions(format!("image of size {}x{} does not match the plan for {}x{}", f.nrows(), f.ncols(), m, n)));
+        }
+        let data = self.forward.iter().map(|&k| f[[k / n, k % n]]).collect();
+        let data = Array2::from_shape_vec(self.dim, data).expect("the plan has a pixel per encrypted pixel");
+        Ok(Ciphertext { data, header: self.header.clone() })
+    }
+
+    /// Decrypts `c`, which must have been encrypted with the plan (or with
+    /// the same key, nonce and parameters).
+    pub fn decrypt<T>(&self, c: &Ciphertext<T>) -> Result<Array2<T>, Error>
+    where
+        T: Copy,
+    {
+        if c.data.dim() != self.dim || (c.header.m, c.header.n) != (self.header.m, self.header.n) {
+            return Err(Error::InvalidDimensions(format!("encrypted image of size {}x{} does not match the plan", c.data.nrows(), c.data.ncols())));
+        }
+        if c.header.nonce != self.header.nonce {
+            return Err(Error::InvalidParams("the image was encrypted with another nonce than the plan".to_string()));
+        }
+        let n = self.dim.1;
+        let data = self.inverse.iter().map(|&k| c.data[[k / n, k % n]]).collect();
+        Ok(Array2::from_shape_vec((self.header.m, self.header.n), data).expect("the plan has a pixel per plain pixel"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{decrypt, encrypt_with_nonce, LevelKeys, PermutationMode};
+
+    #[test]
+    fn matches_encrypt_and_decrypt() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let nonce = [5; 16];
+        let hierarchies: [&[(usize, usize)]; 3] = [&[(16, 16)], &[(32, 32), (8, 4)], &[(32, 16), (16, 8), (4, 4)]];
+        for (m, n) in [(64, 64), (70, 45)] {
+            let f = Array2::from_shape_fn((m, n), |(i, j)| (i * n + j) as u16);
+            for blocks in hierarchies {
+                for permutation in [PermutationMode::Both, PermutationMode::Blocks, PermutationMode::Pixels] {
+                    for level_keys in [LevelKeys::Shared, LevelKeys::Derived] {
+                        let params = HcieParams { permutation, level_keys, ..HcieParams::with_blocks(blocks) };
+                        let plan = PermutationPlan::new(m, n, &params, &key, &nonce).unwrap();
+                        let c = plan.encrypt(&f).unwrap();
+                        assert_eq!(c, encrypt_with_nonce(&f, &params, &key, &nonce).unwrap());
+                        assert_eq!(plan.decrypt(&c).unwrap(), f);
+                        assert_eq!(decrypt(&c, &params, &key).unwrap(), f);
+                        assert_eq!(PermutationPlan::for_header(&c.header, &params, &key).unwrap(), plan);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_diffusion_and_other_images() {
+        let key = SecretKey::new(0.1234, 3.97).unwrap();
+        let params = HcieParams { diffusion: Diffusion::Xor, ..HcieParams::new(8, 8) };
+        assert!(PermutationPlan::new(16, 16, &params, &key, &[0; 16]).is_err());
+        let plan = PermutationPlan::new(16, 16, &HcieParams::new(8, 8), &key, &[0; 16]).unwrap();
+        assert!(plan.encrypt(&Array2::<u8>::zeros((16, 8))).is_err());
+        let mut c = plan.encrypt(&Array2::<u8>::zeros((16, 16))).unwrap();
+        c.header.nonce = Some([1; 16]);
+        assert!(plan.decrypt(&c).is_err());
+    }
+}
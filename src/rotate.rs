@@ -2,14 +2,20 @@ use ndarray::{ArrayViewMut1, ArrayViewMut2};
 
 /// Rotates the `i`-th row of `f` by `p` positions
 /// in the left or right direction (defined by `b`).
-pub fn rolr(f: &mut ArrayViewMut2<u8>, i: usize, p: usize, b: u8) {
+pub fn rolr<T>(f: &mut ArrayViewMut2<T>, i: usize, p: usize, b: u8)
+where
+    T: Copy,
+{
     let m = f.shape()[0]; // number of rows
     assert!(i < m);
 
     let mut g = f.row_mut(i);
     let n = g.len();
     let p = p % n;
-    let g = g.as_slice_mut().unwrap();
+    // the row is contiguous unless f is a column-major view
+    let Some(g) = g.as_slice_mut() else {
+        return rotate(f.row_mut(i), p, b);
+    };
     match b {
         0 => {
             g.rotate_left(p);
@@ -25,7 +31,10 @@ pub fn rolr(f: &mut ArrayViewMut2<u8>, i: usize, p: usize, b: u8) {
 
 /// Rotates the `j`-th column of `f` by `p` positions
 /// in the up or down direction (defined by `b`).
-pub fn roud(f: &mut ArrayViewMut2<u8>, j: usize, p: usize, b: u8) {
+pub fn roud<T>(f: &mut ArrayViewMut2<T>, j: usize, p: usize, b: u8)
+where
+    T: Copy,
+{
     let n = f.shape()[1];
     assert!(j < n);
 
@@ -41,22 +50,22 @@ pub fn roud(f: &mut ArrayViewMut2<u8>, j: usize, p: usize, b: u8) {
 /// Rotates all elements satisfying `i + j == k`,
 /// in the lower left (when b = 0) or upper right (when b = 1) direction,
 /// by `p` positions.
-pub fn rour(f: &mut ArrayViewMut2<u8>, k: usize, p: usize, b: u8) {
+pub fn rour<T>(f: &mut ArrayViewMut2<T>, k: usize, p: usize, b: u8)
+where
+    T: Copy,
+{
     let m = f.shape()[0];
     let n = f.shape()[1];
     assert!(k <= m + n - 2);
 
-    let g = f.as_slice_memory_order_mut().unwrap();
-
     let min_j = k.saturating_sub(m - 1);
     let max_j = k.min(n - 1);
     let min_i = k.saturating_sub(n - 1);
     let max_i = k.min(m - 1);
-    let g_ptr = g.as_mut_ptr();
     let ptrs = (min_i..=max_i)
         .rev()
         .zip(min_j..=max_j)
-        .map(|(i, j)| unsafe { g_ptr.add(i * n + j) })
+        .map(|(i, j)| element_ptr(f, i, j))
         .collect::<Vec<_>>();
 
     rotate_ptrs(&ptrs, p, b);
@@ -65,27 +74,36 @@ pub fn rour(f: &mut ArrayViewMut2<u8>, k: usize, p: usize, b: u8) {
 /// Rotates all elements satisfying `i - j == l`,
 /// in the upper left (when b = 0) or lower right (when b = 1) direction,
 /// by `p` positions.
-pub fn roul(f: &mut ArrayViewMut2<u8>, l: isize, p: usize, b: u8) {
+pub fn roul<T>(f: &mut ArrayViewMut2<T>, l: isize, p: usize, b: u8)
+where
+    T: Copy,
+{
     let m = f.shape()[0];
     let n = f.shape()[1];
     assert!(l >= 1 - n as isize, "l = {}, n = {}", l, n);
     assert!(l <= (m - 1) as isize);
 
-    let g = f.as_slice_memory_order_mut().unwrap();
     let min_j = std::cmp::max(0, -l) as usize;
     let max_j = std::cmp::min(n - 1, (m as isize - 1 - l) as usize);
     let min_i = std::cmp::max(0, l) as usize;
     let max_i = std::cmp::min(m - 1, (l + n as isize - 1) as usize);
 
-    let g_ptr = g.as_mut_ptr();
     let ptrs = (min_i..=max_i)
         .zip(min_j..=max_j)
-        .map(|(i, j)| unsafe { g_ptr.add(i * n + j) })
+        .map(|(i, j)| element_ptr(f, i, j))
         .collect::<Vec<_>>();
 
     rotate_ptrs(&ptrs, p, b);
 }
 
+/// Pointer to `f[[i, j]]`, following the strides of `f`, so that the
+/// diagonals of a block of a larger array can be rotated in place.
+fn element_ptr<T>(f: &mut ArrayViewMut2<T>, i: usize, j: usize) -> *mut T {
+    let (s_i, s_j) = (f.strides()[0], f.strides()[1]);
+    // in bounds, since i < m and j < n
+    unsafe { f.as_mut_ptr().offset(i as isize * s_i + j as isize * s_j) }
+}
+
 fn rotate_ptrs<T>(ptrs: &[*mut T], p: usize, b: u8)
 where
     T: Copy,
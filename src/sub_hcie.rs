@@ -1,52 +1,86 @@
 use ndarray::ArrayViewMut2;
 
-use crate::{rotate::{rolr, roud, rour, roul}, logistic::{SecretKey, logistic_bitsequence}};
+use crate::{chaotic::ChaoticMap, keystream::{Bits, Keystream}, params::HcieParams, rotate::{rolr, roud, rour, roul}};
 
+/// Direction in which a [`SubHCIE`] permutes its input.
 #[derive(Clone, Copy)]
 pub enum Operation {
     Encrypt,
     Decrypt
 }
 
+/// Permutes `s_m x s_n` matrices by rotating their rows, columns and diagonals,
+/// as dictated by a pseudo-random bit sequence.
+///
+/// Every call to [`SubHCIE::apply`] reads the next
+/// `n_iter * (3*s_m + 3*s_n - 2)` bits of a [`Keystream`]. When decrypting,
+/// the same bits undo, in reverse order, the rotations done when encrypting.
 pub struct SubHCIE {
-    offset: usize,
     n_iter: usize,
-    bit_sequence: Vec<u8>,
     alpha: usize,
     beta: usize,
     gamma: usize,
-    op: Operation
+    op: Operation,
+    // bits of the current matrix
+    bits: Vec<u8>,
 }
 
 impl SubHCIE {
-    pub fn new(key: &SecretKey, n_iter: usize, op: Operation, alpha: usize, beta: usize, gamma: usize, bit_sequence: Vec<u8>, init_offset: usize) -> Self {
+    pub fn new(params: &HcieParams, op: Operation) -> Self {
         Self {
-            offset: init_offset,
-            n_iter,
-            bit_sequence,
+            n_iter: params.n_iter,
             op,
-            alpha,
-            beta,
-            gamma
+            alpha: params.alpha,
+            beta: params.beta,
+            gamma: params.gamma,
+            bits: Vec::new(),
         }
     }
 
-    pub fn apply(&mut self, f: &mut ArrayViewMut2<u8>) {
+    pub fn apply<T, M>(&mut self, f: &mut ArrayViewMut2<T>, keystream: &mut Keystream<M>)
+    where
+        T: Copy,
+        M: ChaoticMap + ?Sized,
+    {
+        let mut bits = std::mem::take(&mut self.bits);
+        bits.resize(self.bits_for(f.dim()), 0);
+        keystream.read(&mut bits);
+        self.apply_bits(f, bits.as_slice());
+        self.bits = bits;
+    }
+
+    /// Permutes `f` as [`SubHCIE::apply`], with bits read beforehand from the
+    /// keystream, e.g. to permute several matrices in parallel.
+    ///
+    /// `bits` must hold at least [`bits_for(f.dim())`](Self::bits_for) bits.
+    pub fn apply_bits<T, B>(&self, f: &mut ArrayViewMut2<T>, bits: &B)
+    where
+        T: Copy,
+        B: Bits + ?Sized,
+    {
         match self.op {
-            Operation::Encrypt => self.encrypt(f),
-            Operation::Decrypt => self.decrypt(f)
+            Operation::Encrypt => self.encrypt(f, bits),
+            Operation::Decrypt => self.decrypt(f, bits)
         }
     }
 
-    /// Given a pseudo-random bit sequence b
+    /// Number of bits read to permute an `s_m x s_n` matrix.
+    pub fn bits_for(&self, (s_m, s_n): (usize, usize)) -> usize {
+        (3*s_m + 3*s_n - 2) * self.n_iter
+    }
+
+    /// Given the pseudo-random bits b of the matrix,
     /// this function is used to permute an s_m x s_n matrix
-    fn encrypt(&mut self, f: &mut ArrayViewMut2<u8>) {
+    fn encrypt<T, B>(&self, f: &mut ArrayViewMut2<T>, bits: &B)
+    where
+        T: Copy,
+        B: Bits + ?Sized,
+    {
         let s_m = f.shape()[0];
         let s_n = f.shape()[1];
-        let b_len = self.bit_sequence.len();
-        let bit = |i: usize| self.bit_sequence[i % b_len];
+        let bit = |i: usize| bits.bit(i);
         for iter in 0..self.n_iter {
-            let q = self.offset + (3*s_m + 3*s_n - 2) * iter;
+            let q = (3*s_m + 3*s_n - 2) * iter;
             let p = self.alpha + self.beta * bit(q) as usize + self.gamma * bit(q + 1) as usize;
             for i in 0..s_m {
                 rolr(f, i, p, bit(i + q));
@@ -64,24 +98,22 @@ impl SubHCIE {
                 roul(f, l, p, bit(temp as usize));
             }
         }
-        self.offset += (3*s_m + 3*s_n - 2) * self.n_iter
     }
 
     pub fn set_op(&mut self, op: Operation) {
         self.op = op;
     }
 
-    pub fn set_offset(&mut self, offset: usize) {
-        self.offset = offset;
-    }
-
-    fn decrypt(&mut self, f: &mut ArrayViewMut2<u8>) {
+    fn decrypt<T, B>(&self, f: &mut ArrayViewMut2<T>, bits: &B)
+    where
+        T: Copy,
+        B: Bits + ?Sized,
+    {
         let s_m = f.shape()[0];
         let s_n = f.shape()[1];
-        let b_len = self.bit_sequence.len();
-        let bit = |i: usize| self.bit_sequence[i % b_len];
+        let bit = |i: usize| bits.bit(i);
         for iter in (0..self.n_iter).rev() {
-            let q = self.offset + (3*s_m + 3*s_n - 2) * iter;
+            let q = (3*s_m + 3*s_n - 2) * iter;
             let p = self.alpha + self.beta * bit(q) as usize + self.gamma * bit(q + 1) as usize;
             
             for l in ((-(s_n as isize) + 1)..=(s_m as isize - 1)).rev() {
@@ -100,6 +132,5 @@ impl SubHCIE {
                 rolr(f, i, p, bit(i + q) ^ 1);
             }
         }
-        self.offset -= (3*s_m + 3*s_n - 2) * self.n_iter
     }
 }
\ No newline at end of file